@@ -0,0 +1,225 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use nostr::bip39::Mnemonic;
+use nostr::bitcoin::util::bip32::DerivationPath;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::MineArgs;
+use crate::miner::{FoundKey, MiningStats};
+use crate::mnemonic::MnemonicLanguage;
+use crate::output::KeyRecord;
+
+/// How often the state file is written while mining
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The command line arguments that define what is searched for. A resumed
+/// job must search for the same thing, or the statistics would be wrong.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobDefinition {
+    pub difficulty: u8,
+    pub vanity_prefix: String,
+    pub vanity_npub_prefixes: String,
+    pub vanity_npub_suffixes: String,
+    pub vanity_npub_contains: String,
+    pub vanity_contains: String,
+    pub within: Option<usize>,
+    pub npub_pattern: String,
+    pub hex_pattern: String,
+    pub no_scaling: bool,
+    pub incremental: bool,
+    pub word_count: usize,
+    #[serde(default)]
+    pub split_key: Option<String>,
+    /// Word list of the mnemonics generated with `-g`
+    #[serde(default)]
+    pub language: Option<MnemonicLanguage>,
+    /// Whether the mnemonics have a passphrase. The passphrase itself is
+    /// not stored, not even hashed, and must be given again.
+    #[serde(default)]
+    pub passphrase: bool,
+}
+
+impl JobDefinition {
+    pub fn from_args(args: &MineArgs) -> Self {
+        JobDefinition {
+            difficulty: args.difficulty,
+            vanity_prefix: args.vanity_prefix.clone(),
+            vanity_npub_prefixes: args.vanity_npub_prefixes_raw_input.clone(),
+            vanity_npub_suffixes: args.vanity_npub_suffixes_raw_input.clone(),
+            vanity_npub_contains: args.vanity_npub_contains_raw_input.clone(),
+            vanity_contains: args.vanity_contains_raw_input.clone(),
+            within: args.within,
+            npub_pattern: args.npub_pattern.clone(),
+            hex_pattern: args.hex_pattern.clone(),
+            no_scaling: args.no_scaling,
+            incremental: args.incremental,
+            word_count: args.word_count,
+            split_key: args.split_key.clone(),
+            language: (args.word_count > 0).then(|| args.language.unwrap_or_default()),
+            passphrase: !args.mnemonic_passphrase.is_empty(),
+        }
+    }
+
+    /// Set the arguments to search for this job again
+    pub fn apply(&self, args: &mut MineArgs) {
+        args.difficulty = self.difficulty;
+        args.vanity_prefix = self.vanity_prefix.clone();
+        args.vanity_npub_prefixes_raw_input = self.vanity_npub_prefixes.clone();
+        args.vanity_npub_suffixes_raw_input = self.vanity_npub_suffixes.clone();
+        args.vanity_npub_contains_raw_input = self.vanity_npub_contains.clone();
+        args.vanity_contains_raw_input = self.vanity_contains.clone();
+        args.within = self.within;
+        args.npub_pattern = self.npub_pattern.clone();
+        args.hex_pattern = self.hex_pattern.clone();
+        args.no_scaling = self.no_scaling;
+        args.incremental = self.incremental;
+        args.word_count = self.word_count;
+        args.split_key = self.split_key.clone();
+        args.language = self.language;
+    }
+}
+
+/// Progress of a job, written to the state file to resume it later. As the
+/// file stays on disk after the run, only the public key of the best near
+/// miss is kept, its secret key is only reported by the run that found it.
+/// The file is still only readable by its owner.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub job: JobDefinition,
+    pub count: Option<u64>,
+    pub max_iterations: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub iterations: u64,
+    pub matches: u64,
+    pub elapsed_seconds: f64,
+    /// Difficulty reached with difficulty scaling
+    pub best_difficulty: u8,
+    pub best_near_miss: Option<KeyRecord>,
+}
+
+impl Checkpoint {
+    pub fn new(args: &MineArgs, best_difficulty: u8) -> Self {
+        Checkpoint {
+            job: JobDefinition::from_args(args),
+            count: args.count,
+            max_iterations: args.max_iterations,
+            timeout: args.timeout,
+            iterations: 0,
+            matches: 0,
+            elapsed_seconds: 0.0,
+            best_difficulty,
+            best_near_miss: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the state file {}: {e}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Write the checkpoint to a temporary file first and move it in place,
+    /// so that a crash while writing doesn't lose the previous one
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temporary)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Continue on the command line the job of the checkpoint. The limits
+    /// can be changed, the search cannot.
+    pub fn resume(&self, args: &mut MineArgs) -> Result<()> {
+        let given = JobDefinition::from_args(args);
+        match (self.job.passphrase, given.passphrase) {
+            (true, false) => {
+                return Err("The passphrase is not saved in the state file, give it again".into())
+            }
+            (false, true) => return Err("The search of the state file has no passphrase".into()),
+            _ => {}
+        }
+        let searched = JobDefinition {
+            passphrase: false,
+            ..given.clone()
+        };
+        if searched != JobDefinition::default() && given != self.job {
+            return Err(
+                "The search on the command line is different from the one of the state file".into(),
+            );
+        }
+        self.job.apply(args);
+        args.count = args.count.or(self.count);
+        args.max_iterations = args.max_iterations.or(self.max_iterations);
+        args.timeout = args.timeout.or(self.timeout);
+        Ok(())
+    }
+
+    /// Statistics of the runs so far, to resume the mining with
+    pub fn stats(&self) -> Result<MiningStats> {
+        Ok(MiningStats {
+            iterations: self.iterations,
+            matches: self.matches,
+            elapsed: Duration::from_secs_f64(self.elapsed_seconds),
+            best_match: self.best_near_miss.as_ref().map(found_key).transpose()?,
+        })
+    }
+
+    pub fn update(&mut self, stats: &MiningStats) {
+        self.iterations = stats.iterations;
+        self.matches = stats.matches;
+        self.elapsed_seconds = stats.elapsed.as_secs_f64();
+        self.best_near_miss = stats
+            .best_match
+            .as_ref()
+            .map(|best| KeyRecord::from(best).public());
+    }
+}
+
+fn found_key(record: &KeyRecord) -> Result<FoundKey> {
+    let tweak = record
+        .tweak
+        .as_deref()
+        .map(SecretKey::from_str)
+        .transpose()?;
+    // State files of earlier versions also hold the secret key
+    let keys = match &record.hex_secret_key {
+        Some(secret_key) => Keys::new(SecretKey::from_str(secret_key)?),
+        None => Keys::from_public_key(XOnlyPublicKey::from_str(&record.hex_public_key)?),
+    };
+    Ok(FoundKey {
+        keys,
+        tweak,
+        mnemonic: record
+            .mnemonic
+            .as_deref()
+            .map(Mnemonic::from_str)
+            .transpose()?,
+        derivation_path: record
+            .derivation_path
+            .as_deref()
+            .map(DerivationPath::from_str)
+            .transpose()?,
+        target: record.target.clone(),
+        leading_zero_bits: record.leading_zero_bits,
+        similarity: record.similarity,
+        iterations: record.iterations,
+        elapsed: Duration::from_secs_f64(record.elapsed_seconds),
+    })
+}
@@ -1,5 +1,17 @@
-use clap::Parser;
-use regex::Regex;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::error::RanaError;
+use crate::keysource::DerivationScan;
+use crate::matcher::{HEX_LEN, NPUB_BODY_LEN};
+use crate::mnemonic::{parse_mnemonic, MnemonicLanguage, MAX_ACCOUNT, WORD_COUNTS};
+use crate::nip49::{KeySecurity, LOG_N_DEFAULT};
+use crate::output::OutputFormat;
+use crate::pattern::{PatternFormat, PatternMatcher, BECH32_ALPHABET, HEX_ALPHABET};
+use crate::splitkey::parse_public_key;
 
 #[derive(Parser)]
 #[command(
@@ -20,9 +32,161 @@ use regex::Regex;
 
 {all-args}{after-help}
 ",
-    version
+    version,
+    args_conflicts_with_subcommands = true
 )]
 pub struct CLIArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Mining without a subcommand, kept for existing scripts
+    #[command(flatten, next_help_heading = "Deprecated options, use `rana mine`")]
+    pub mine: MineArgs,
+
+    #[arg(
+        short = 'r',
+        long = "restore",
+        required = false,
+        help = "Deprecated, use `rana mnemonic restore`"
+    )]
+    pub mnemonic: Option<String>,
+}
+
+// Parsed once, the size of the mining arguments doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Mine keys matching a difficulty or a vanity target
+    Mine(MineArgs),
+    /// Generate or restore mnemonics (BIP-39)
+    #[command(subcommand)]
+    Mnemonic(MnemonicCommand),
+    /// Convert a key between hex and bech32 (npub, nsec)
+    Convert(ConvertArgs),
+    /// Show the formats and the proof of work of a key
+    Inspect(InspectArgs),
+    /// Add a tweak found with --split-key to your private key, offline
+    Combine(CombineArgs),
+    /// Mine NIP-13 proof of work for an event
+    Event(EventArgs),
+}
+
+#[derive(Subcommand)]
+pub enum MnemonicCommand {
+    /// Generate a new mnemonic and show its keys
+    Generate(MnemonicGenerateArgs),
+    /// Show the keys of a mnemonic
+    Restore(MnemonicRestoreArgs),
+}
+
+#[derive(Args)]
+pub struct MnemonicGenerateArgs {
+    #[arg(
+        short,
+        long,
+        default_value_t = 12,
+        help = "Number of words: 12, 15, 18, 21 or 24"
+    )]
+    pub words: usize,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = MnemonicLanguage::English,
+        help = "Language of the BIP-39 word list"
+    )]
+    pub language: MnemonicLanguage,
+    #[arg(
+        short,
+        long,
+        default_value = "",
+        help = "Passphrase to derive the keys with"
+    )]
+    pub passphrase: String,
+    #[command(flatten)]
+    pub accounts: AccountArgs,
+}
+
+#[derive(Args)]
+pub struct MnemonicRestoreArgs {
+    #[arg(help = "The mnemonic, in quotes with the words separated by spaces.
+Asked for without echo, or read from stdin, when left out
+or -, to keep it out of the shell history.")]
+    pub mnemonic: Option<String>,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        help = "Language of the BIP-39 word list [default: the one of the
+words]"
+    )]
+    pub language: Option<MnemonicLanguage>,
+    #[arg(
+        short,
+        long,
+        default_value = "",
+        help = "Passphrase the keys were derived with"
+    )]
+    pub passphrase: String,
+    #[command(flatten)]
+    pub accounts: AccountArgs,
+}
+
+/// The NIP-06 accounts to show the keys of
+#[derive(Args, Default)]
+pub struct AccountArgs {
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(..=MAX_ACCOUNT as i64),
+        conflicts_with = "accounts",
+        help = "Show the keys of this NIP-06 account instead of account 0"
+    )]
+    pub account: Option<u32>,
+    #[arg(
+        long,
+        value_parser = parse_accounts,
+        help = "Show the keys of every NIP-06 account in this range, like 0-4"
+    )]
+    pub accounts: Option<RangeInclusive<u32>>,
+}
+
+impl AccountArgs {
+    pub fn range(&self) -> RangeInclusive<u32> {
+        match (self.account, &self.accounts) {
+            (Some(account), _) => account..=account,
+            (None, Some(accounts)) => accounts.clone(),
+            (None, None) => 0..=0,
+        }
+    }
+
+    /// Whether accounts were chosen, and shown with their derivation path
+    pub fn is_set(&self) -> bool {
+        self.account.is_some() || self.accounts.is_some()
+    }
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    #[arg(help = "An npub, an nsec, or a key as hex")]
+    pub key: String,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "The hex key is a private key, convert it to an nsec"
+    )]
+    pub secret: bool,
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    #[arg(help = "An npub, an nsec, or a key as hex")]
+    pub key: String,
+    #[arg(long, default_value_t = false, help = "The hex key is a private key")]
+    pub secret: bool,
+}
+
+#[derive(Args)]
+pub struct MineArgs {
     #[arg(
         short,
         long,
@@ -59,6 +223,48 @@ in npub format (Bech32 encoding). Specify multiple vanity
 targets as a comma-separated list."
     )]
     pub vanity_npub_suffixes_raw_input: String,
+    #[arg(
+        long = "vanity-n-contains",
+        required = false,
+        default_value = "",
+        help = "Enter a string your public key should contain anywhere when
+expressed in npub format (Bech32 encoding). Specify multiple
+vanity targets as a comma-separated list."
+    )]
+    pub vanity_npub_contains_raw_input: String,
+    #[arg(
+        long = "vanity-contains",
+        required = false,
+        default_value = "",
+        help = "Enter a string your public key should contain anywhere when
+expressed as hexadecimal. Specify multiple vanity targets as
+a comma-separated list."
+    )]
+    pub vanity_contains_raw_input: String,
+    #[arg(
+        long = "within",
+        required = false,
+        help = "Only accept the strings of --vanity-n-contains or
+--vanity-contains within the first N characters (after npub1)."
+    )]
+    pub within: Option<usize>,
+    #[arg(
+        long = "pattern",
+        required = false,
+        default_value = "",
+        help = "Enter a regular expression your public key should match when
+expressed in npub format, e.g. '^npub1(rana|h0dl)[0-9]{2}'
+or '.*h0dl$'. Only characters supported by Bech32 can match."
+    )]
+    pub npub_pattern: String,
+    #[arg(
+        long = "hex-pattern",
+        required = false,
+        default_value = "",
+        help = "Enter a regular expression your public key should match when
+expressed as hexadecimal, e.g. '^(dead|beef)'."
+    )]
+    pub hex_pattern: String,
     #[arg(
         short = 'c',
         long = "cores",
@@ -67,19 +273,11 @@ targets as a comma-separated list."
     )]
     pub num_cores: usize,
 
-    #[arg(
-        short = 'r',
-        long = "restore",
-        help = "Restore from mnemonic to public private key",
-        default_value_t = String::from(""),
-        required = false
-    )]
-    pub mnemonic: String,
-
     #[arg(
         short = 'g',
         long = "generate",
-        help = "Generate mnemonic using wordcount. Should be 12,18 or 24",
+        help = "Mine keys generated from mnemonics of this many words: 12,
+15, 18, 21 or 24",
         default_value_t = 0,
         required = false
     )]
@@ -88,11 +286,40 @@ targets as a comma-separated list."
     #[arg(
         short = 'p',
         long = "passphrase",
-        help = "Passphrase used for restoring mnemonic to keypair",
+        help = "Passphrase of the mnemonics the keys are generated from, with
+-g or --mnemonic",
         default_value_t = String::from(""),
         required = false
     )]
     pub mnemonic_passphrase: String,
+    #[arg(
+        long = "mnemonic",
+        value_name = "MNEMONIC",
+        required = false,
+        help = "Mine the NIP-06 keys of this mnemonic instead of random keys,
+scanning its accounts or address indices (see --scan), so
+that the key found can be restored from your backup. Use -
+to type it without echo or read it from stdin."
+    )]
+    pub scan_mnemonic: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        required = false,
+        help = "Part of the NIP-06 path m/44'/1237'/<account>'/0/<index> to
+scan with --mnemonic [default: account]. With -g, a single
+mnemonic is generated and scanned."
+    )]
+    pub scan: Option<DerivationScan>,
+    #[arg(
+        long,
+        value_enum,
+        required = false,
+        help = "Language of the BIP-39 word list of the mnemonics, with -g
+[default: english] or --mnemonic [default: the one of the
+words]"
+    )]
+    pub language: Option<MnemonicLanguage>,
 
     #[arg(
         short,
@@ -117,15 +344,270 @@ targets as a comma-separated list."
         help = "When true, disables difficulty scaling and keeps it fixed throughout."
     )]
     pub no_scaling: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Walk the curve from a random key by adding the generator point
+instead of generating every key from scratch. Much faster."
+    )]
+    pub incremental: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Compare the speed of the ways of generating keys, measure how
+mining scales with the number of threads and exit."
+    )]
+    pub benchmark: bool,
+    #[arg(
+        long,
+        required = false,
+        help = "Stop after finding this many matching keys."
+    )]
+    pub count: Option<u64>,
+    #[arg(
+        long,
+        required = false,
+        value_parser = parse_duration,
+        help = "Stop after this much time, in seconds or with a unit like
+90s, 30m, 12h or 2d. With --resume, the time of this run."
+    )]
+    pub timeout: Option<Duration>,
+    #[arg(long, required = false, help = "Stop after trying this many keys.")]
+    pub max_iterations: Option<u64>,
+    #[arg(
+        long,
+        required = false,
+        value_parser = parse_duration,
+        num_args = 0..=1,
+        default_missing_value = "10s",
+        help = "Report the hash rate, the best match and the chances of having
+found a match on stderr, or as records with --output-format
+ndjson, every 10 seconds or at this interval."
+    )]
+    pub progress: Option<Duration>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Format of the found keys written to stdout. Progress and other
+messages are always written to stderr."
+    )]
+    pub output_format: OutputFormat,
+    #[arg(
+        long = "out",
+        required = false,
+        help = "Append the found keys to this file, created readable by you
+only, and only show the public keys."
+    )]
+    pub out: Option<PathBuf>,
+    #[arg(
+        long = "ncryptsec-password",
+        env = "RANA_NCRYPTSEC_PASSWORD",
+        hide_env_values = true,
+        required = false,
+        help = "Also show the found private keys encrypted with this password
+as ncryptsec (NIP-49), which is used for the QR code instead
+of the nsec."
+    )]
+    pub ncryptsec_password: Option<String>,
+    #[arg(
+        long = "ncryptsec-log-n",
+        default_value_t = LOG_N_DEFAULT,
+        help = "scrypt cost of the ncryptsec encryption, as a power of 2. Every
+step doubles the time and memory needed to try a password."
+    )]
+    pub ncryptsec_log_n: u8,
+    #[arg(
+        long = "key-security",
+        value_enum,
+        default_value_t = KeySecurity::Unknown,
+        help = "How the private keys were handled, as stored in the ncryptsec"
+    )]
+    pub key_security: KeySecurity,
+    #[arg(
+        long = "state-file",
+        required = false,
+        help = "Regularly save the progress of the search to this file, to
+continue it later with --resume. It only holds the public
+key of the best near miss, never secret keys or mnemonics."
+    )]
+    pub state_file: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "state_file",
+        help = "Continue the search saved in --state-file, adding up the
+statistics. The limits can be changed, the search cannot.
+The timeout starts over, the other limits are totals. The
+passphrase is not saved, give it again with -p."
+    )]
+    pub resume: bool,
+    #[arg(
+        long,
+        required = false,
+        help = "Generate the keys deterministically from this seed, to replay
+a search. Never use the keys found this way!"
+    )]
+    pub seed: Option<u64>,
+    #[arg(
+        long = "split-key",
+        required = false,
+        help = "Mine for a customer who only gives their public key (npub or
+hex). Only the tweak to add to their private key is found,
+which they combine with `rana combine`."
+    )]
+    pub split_key: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CombineArgs {
+    #[arg(
+        long = "secret",
+        env = "RANA_SECRET_KEY",
+        hide_env_values = true,
+        help = "Your private key (nsec or hex), whose public key was given for
+split-key mining"
+    )]
+    pub secret_key: String,
+    #[arg(long, help = "The tweak found by split-key mining (hex)")]
+    pub tweak: String,
+    #[arg(
+        long = "expect",
+        required = false,
+        help = "The vanity public key (npub or hex) the combined key must have"
+    )]
+    pub expected: Option<String>,
+}
+
+#[derive(Args)]
+pub struct EventArgs {
+    #[arg(
+        short,
+        long,
+        help = "Number of leading zero bits the event id should have"
+    )]
+    pub difficulty: u8,
+    #[arg(
+        long = "event",
+        default_value = "-",
+        help = "File with the unsigned event as JSON (kind, content, tags,
+created_at, pubkey), or - for stdin"
+    )]
+    pub event: PathBuf,
+    #[arg(
+        short = 'c',
+        long = "cores",
+        default_value_t = num_cpus::get(),
+        help = "Number of processor cores to use"
+    )]
+    pub num_cores: usize,
+    #[arg(
+        long = "secret",
+        env = "RANA_SECRET_KEY",
+        hide_env_values = true,
+        required = false,
+        help = "Sign the event with this private key (nsec or hex)"
+    )]
+    pub secret_key: Option<String>,
+    #[arg(
+        long = "key-difficulty",
+        required = false,
+        conflicts_with = "secret_key",
+        help = "Sign the event with a new key, mined with this number of
+leading zero bits"
+    )]
+    pub key_difficulty: Option<u8>,
+}
+
+impl MineArgs {
+    pub fn vanity_npub_prefixes(&self) -> Vec<String> {
+        split_targets(&self.vanity_npub_prefixes_raw_input)
+    }
+
+    pub fn vanity_npub_suffixes(&self) -> Vec<String> {
+        split_targets(&self.vanity_npub_suffixes_raw_input)
+    }
+
+    pub fn vanity_npub_contains(&self) -> Vec<String> {
+        split_targets(&self.vanity_npub_contains_raw_input)
+    }
+
+    pub fn vanity_contains(&self) -> Vec<String> {
+        split_targets(&self.vanity_contains_raw_input)
+    }
+
+    /// What is scanned when mining the derivations of a single mnemonic
+    pub fn derivation_scan(&self) -> Option<DerivationScan> {
+        match (self.scan, &self.scan_mnemonic) {
+            (None, Some(_)) => Some(DerivationScan::Account),
+            (scan, _) => scan,
+        }
+    }
+}
+
+/// Parse a duration like `90`, `90s`, `30m`, `12h` or `2d`
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(position) => input.split_at(position),
+        None => (input, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: '{input}'"))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit: '{unit}' (use s, m, h or d)"
+            ))
+        }
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parse a range of accounts, inclusive, like `0-4`, or a single account
+pub fn parse_accounts(input: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse = |account: &str| match account.trim().parse::<u32>() {
+        Ok(account) if account <= MAX_ACCOUNT => Ok(account),
+        _ => Err(format!(
+            "Invalid account: '{account}' (accounts go from 0 to {MAX_ACCOUNT})"
+        )),
+    };
+    let (start, end) = match input.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(input)?, parse(input)?),
+    };
+    if start > end {
+        return Err(format!("Invalid range of accounts: '{input}' is empty"));
+    }
+    Ok(start..=end)
+}
+
+// Split a comma-separated list of vanity targets
+fn split_targets(raw_input: &str) -> Vec<String> {
+    raw_input
+        .split(',')
+        .filter(|target| !target.is_empty())
+        .map(|target| target.to_string())
+        .collect()
 }
 
-pub fn check_args(
-    difficulty: u8,
-    vanity_prefix: &str,
-    vanity_npub_prefixes: &Vec<String>,
-    vanity_npub_suffixes: &Vec<String>,
-    num_cores: usize,
-) {
+/// Check that the arguments describe a search that can be done
+pub fn check_args(args: &MineArgs) -> Result<(), RanaError> {
+    let difficulty = args.difficulty;
+    let vanity_prefix = args.vanity_prefix.as_str();
+    let vanity_npub_prefixes = args.vanity_npub_prefixes();
+    let vanity_npub_suffixes = args.vanity_npub_suffixes();
+    let vanity_npub_contains = args.vanity_npub_contains();
+    let vanity_contains = args.vanity_contains();
+    let npub_pattern = args.npub_pattern.as_str();
+    let hex_pattern = args.hex_pattern.as_str();
+    let num_cores = args.num_cores;
+
     // Check the public key requirements
     let mut requirements_count: u8 = 0;
     if difficulty > 0 {
@@ -137,54 +619,298 @@ pub fn check_args(
     if !vanity_npub_prefixes.is_empty() || !vanity_npub_suffixes.is_empty() {
         requirements_count += 1;
     }
+    if !npub_pattern.is_empty() {
+        requirements_count += 1;
+    }
+    if !hex_pattern.is_empty() {
+        requirements_count += 1;
+    }
+    if !vanity_npub_contains.is_empty() {
+        requirements_count += 1;
+    }
+    if !vanity_contains.is_empty() {
+        requirements_count += 1;
+    }
 
     if requirements_count > 1 {
-        panic!("You can cannot specify more than one requirement. You should choose between difficulty or any of the vanity formats.");
+        return Err(RanaError::MultipleRequirements);
     }
 
-    if vanity_prefix.len() > 64 {
-        panic!("The vanity prefix cannot be longer than 64 characters.");
+    check_hex("-v, --vanity", vanity_prefix)?;
+    check_len("-v, --vanity", vanity_prefix, HEX_LEN)?;
+
+    for vanity_npub_prefix in &vanity_npub_prefixes {
+        check_bech32("-n, --vanity-n-prefix", vanity_npub_prefix)?;
+        check_len("-n, --vanity-n-prefix", vanity_npub_prefix, NPUB_BODY_LEN)?;
     }
 
-    if !vanity_prefix.is_empty() {
-        // check valid hexa characters
-        let hex_re = Regex::new(r"^([0-9a-f]*)$").unwrap();
-        if !hex_re.is_match(vanity_prefix) {
-            panic!("The vanity prefix can only contain hexadecimal characters.");
-        }
+    for vanity_npub_suffix in &vanity_npub_suffixes {
+        check_bech32("-s, --vanity-n-suffix", vanity_npub_suffix)?;
+        check_len("-s, --vanity-n-suffix", vanity_npub_suffix, NPUB_BODY_LEN)?;
     }
 
-    for vanity_npub_prefix in vanity_npub_prefixes {
-        if !vanity_npub_prefix.is_empty() {
-            let hex_re = Regex::new(r"^([02-9ac-hj-np-z]*)$").unwrap();
-            if !hex_re.is_match(vanity_npub_prefix.as_str()) {
-                panic!("The vanity npub prefix can only contain characters supported by Bech32: 023456789acdefghjklmnpqrstuvwxyz");
+    for vanity_npub_prefix in &vanity_npub_prefixes {
+        for vanity_npub_suffix in &vanity_npub_suffixes {
+            if vanity_npub_prefix.len() + vanity_npub_suffix.len() > NPUB_BODY_LEN {
+                return Err(RanaError::TargetTooLong {
+                    option: "combined vanity npub prefix and suffix",
+                    target: format!("{vanity_npub_prefix}...{vanity_npub_suffix}"),
+                    max: NPUB_BODY_LEN,
+                });
             }
         }
-        if vanity_npub_prefix.len() > 59 {
-            panic!("The vanity npub prefix cannot be longer than 59 characters.");
+    }
+
+    for vanity_npub_infix in &vanity_npub_contains {
+        check_bech32("--vanity-n-contains", vanity_npub_infix)?;
+        check_len(
+            "--vanity-n-contains",
+            vanity_npub_infix,
+            args.within.unwrap_or(NPUB_BODY_LEN),
+        )?;
+    }
+
+    for vanity_infix in &vanity_contains {
+        check_hex("--vanity-contains", vanity_infix)?;
+        check_len(
+            "--vanity-contains",
+            vanity_infix,
+            args.within.unwrap_or(HEX_LEN),
+        )?;
+    }
+
+    if args.within.is_some() && vanity_npub_contains.is_empty() && vanity_contains.is_empty() {
+        return Err(RanaError::MissingOption {
+            option: "--within",
+            requires: "--vanity-n-contains or --vanity-contains",
+        });
+    }
+
+    if !npub_pattern.is_empty() {
+        if let Err(e) = PatternMatcher::new(npub_pattern, PatternFormat::Npub) {
+            return Err(RanaError::InvalidValue {
+                option: "--pattern",
+                reason: e.to_string(),
+            });
         }
     }
 
-    for vanity_npub_suffix in vanity_npub_suffixes {
-        if !vanity_npub_suffix.is_empty() {
-            let hex_re = Regex::new(r"^([02-9ac-hj-np-z]*)$").unwrap();
-            if !hex_re.is_match(vanity_npub_suffix.as_str()) {
-                panic!("The vanity npub suffix can only contain characters supported by Bech32: 023456789acdefghjklmnpqrstuvwxyz");
-            }
+    if !hex_pattern.is_empty() {
+        if let Err(e) = PatternMatcher::new(hex_pattern, PatternFormat::Hex) {
+            return Err(RanaError::InvalidValue {
+                option: "--hex-pattern",
+                reason: e.to_string(),
+            });
         }
-        if vanity_npub_suffix.len() > 59 {
-            panic!("The vanity npub suffix cannot be longer than 59 characters.");
+    }
+
+    if args.incremental && args.word_count > 0 {
+        return Err(RanaError::Conflict {
+            option: "--incremental",
+            other: "-g, --generate",
+            reason: "keys generated from mnemonics cannot be walked incrementally",
+        });
+    }
+
+    if args.qr && args.out.is_some() {
+        return Err(RanaError::Conflict {
+            option: "--qr",
+            other: "--out",
+            reason: "it would show the private key on the terminal",
+        });
+    }
+
+    if args.qr && args.output_format != OutputFormat::Text {
+        return Err(RanaError::Conflict {
+            option: "--qr",
+            other: "--output-format json or ndjson",
+            reason: "the QR code is only printed with the text output",
+        });
+    }
+
+    if args.ncryptsec_password.as_deref() == Some("") {
+        return Err(RanaError::InvalidValue {
+            option: "--ncryptsec-password",
+            reason: "the password cannot be empty".to_string(),
+        });
+    }
+
+    if !(1..=22).contains(&args.ncryptsec_log_n) {
+        return Err(RanaError::InvalidValue {
+            option: "--ncryptsec-log-n",
+            reason: "it must be between 1 and 22 (4 GiB of memory)".to_string(),
+        });
+    }
+
+    if let Some(state_file) = &args.state_file {
+        if !args.resume && state_file.exists() {
+            return Err(RanaError::StateFileExists(state_file.clone()));
+        }
+    }
+
+    if args.seed.is_some() && args.state_file.is_some() {
+        return Err(RanaError::Conflict {
+            option: "--seed",
+            other: "--state-file",
+            reason: "a resumed search would try the same keys again",
+        });
+    }
+
+    if args.word_count > 0 && !WORD_COUNTS.contains(&args.word_count) {
+        return Err(RanaError::InvalidValue {
+            option: "-g, --generate",
+            reason: "mnemonics have 12, 15, 18, 21 or 24 words".to_string(),
+        });
+    }
+
+    if let Some(mnemonic) = &args.scan_mnemonic {
+        parse_mnemonic(mnemonic, args.language.map(Into::into))?;
+        if args.word_count > 0 {
+            return Err(RanaError::Conflict {
+                option: "--mnemonic",
+                other: "-g, --generate",
+                reason: "-g generates new mnemonics",
+            });
+        }
+    } else if args.word_count == 0 {
+        let option = if args.scan.is_some() {
+            Some("--scan")
+        } else if args.language.is_some() {
+            Some("--language")
+        } else {
+            None
+        };
+        if let Some(option) = option {
+            return Err(RanaError::MissingOption {
+                option,
+                requires: "--mnemonic or -g, --generate",
+            });
+        }
+    }
+
+    if args.derivation_scan().is_some() {
+        let other = if args.incremental {
+            Some((
+                "--incremental",
+                "derived keys cannot be walked incrementally",
+            ))
+        } else if args.split_key.is_some() {
+            Some(("--split-key", "the mnemonic of the customer is not known"))
+        } else if args.seed.is_some() {
+            Some((
+                "--seed",
+                "the derivations are always scanned in the same order",
+            ))
+        } else if args.state_file.is_some() {
+            Some((
+                "--state-file",
+                "a resumed search would scan the same indices again",
+            ))
+        } else {
+            None
+        };
+        if let Some((other, reason)) = other {
+            return Err(RanaError::Conflict {
+                option: if args.scan_mnemonic.is_some() {
+                    "--mnemonic"
+                } else {
+                    "--scan"
+                },
+                other,
+                reason,
+            });
+        }
+    }
+
+    if let Some(split_key) = &args.split_key {
+        if let Err(e) = parse_public_key(split_key) {
+            return Err(RanaError::InvalidValue {
+                option: "--split-key",
+                reason: e.to_string(),
+            });
+        }
+        if args.word_count > 0 {
+            return Err(RanaError::Conflict {
+                option: "--split-key",
+                other: "-g, --generate",
+                reason: "the mnemonic of the customer is not known",
+            });
         }
+        if args.qr || args.ncryptsec_password.is_some() {
+            return Err(RanaError::Conflict {
+                option: "--split-key",
+                other: if args.qr {
+                    "--qr"
+                } else {
+                    "--ncryptsec-password"
+                },
+                reason: "there is no private key to show, only a tweak",
+            });
+        }
+    }
+
+    if args.count == Some(0) {
+        return Err(RanaError::InvalidValue {
+            option: "--count",
+            reason: "it must be greater than 0".to_string(),
+        });
+    }
+
+    check_cores(num_cores)
+}
+
+/// Check the number of cores to mine with against the processor
+pub fn check_cores(num_cores: usize) -> Result<(), RanaError> {
+    let available = num_cpus::get();
+    if num_cores == 0 || num_cores > available {
+        return Err(RanaError::InvalidCores {
+            requested: num_cores,
+            available,
+        });
+    }
+    Ok(())
+}
+
+fn check_hex(option: &'static str, target: &str) -> Result<(), RanaError> {
+    match invalid_char(target, HEX_ALPHABET) {
+        Some((position, character)) => Err(RanaError::InvalidHex {
+            option,
+            target: target.to_string(),
+            position,
+            character,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn check_bech32(option: &'static str, target: &str) -> Result<(), RanaError> {
+    match invalid_char(target, BECH32_ALPHABET) {
+        Some((position, character)) => Err(RanaError::InvalidBech32 {
+            option,
+            target: target.to_string(),
+            position,
+            character,
+        }),
+        None => Ok(()),
     }
+}
+
+// First character not in the alphabet, with its position starting at 1
+fn invalid_char(target: &str, alphabet: &str) -> Option<(usize, char)> {
+    target
+        .chars()
+        .enumerate()
+        .find(|(_, c)| !alphabet.contains(*c))
+        .map(|(i, c)| (i + 1, c))
+}
 
-    if num_cores == 0 {
-        panic!("There can be no proof of work if one does not do work (-c, --cores must be greater than 0)");
-    } else if num_cores > num_cpus::get() {
-        panic!(
-            "Your processor has {} cores; cannot set -c, --cores to {}",
-            num_cpus::get(),
-            num_cores
-        );
+fn check_len(option: &'static str, target: &str, max: usize) -> Result<(), RanaError> {
+    if target.chars().count() > max {
+        return Err(RanaError::TargetTooLong {
+            option,
+            target: target.to_string(),
+            max,
+        });
     }
+    Ok(())
 }
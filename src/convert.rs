@@ -0,0 +1,81 @@
+use nostr::prelude::*;
+
+use crate::cli::{ConvertArgs, InspectArgs};
+use crate::error::RanaError;
+use crate::splitkey::{parse_public_key, parse_secret_key};
+use crate::utils::get_leading_zero_bits;
+
+/// A key given on the command line, as bech32 or hex
+pub enum ParsedKey {
+    Public(XOnlyPublicKey),
+    Secret(SecretKey),
+}
+
+impl ParsedKey {
+    /// Hex keys are public keys unless `secret` is set
+    pub fn parse(key: &str, secret: bool) -> Result<Self, RanaError> {
+        let key = key.trim();
+        let parsed = if key.starts_with("nsec1") || (secret && !key.starts_with("npub1")) {
+            parse_secret_key(key).map(ParsedKey::Secret)
+        } else {
+            parse_public_key(key).map(ParsedKey::Public)
+        };
+        parsed.map_err(|e| RanaError::InvalidValue {
+            option: "key",
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        match self {
+            ParsedKey::Public(public_key) => *public_key,
+            ParsedKey::Secret(secret_key) => secret_key.x_only_public_key(SECP256K1).0,
+        }
+    }
+}
+
+/// `rana convert`: the key in the other format, hex to bech32 and back
+pub fn handle_convert(args: &ConvertArgs) -> Result<(), RanaError> {
+    let key = args.key.trim();
+    let converted = match ParsedKey::parse(key, args.secret)? {
+        ParsedKey::Public(public_key) if key.starts_with("npub1") => public_key.to_string(),
+        ParsedKey::Public(public_key) => public_key
+            .to_bech32()
+            .expect("Could not get public key bech32 conversion"),
+        ParsedKey::Secret(secret_key) if key.starts_with("nsec1") => {
+            secret_key.display_secret().to_string()
+        }
+        ParsedKey::Secret(secret_key) => secret_key
+            .to_bech32()
+            .expect("Could not get secret key bech32 conversion"),
+    };
+    println!("{converted}");
+    Ok(())
+}
+
+/// `rana inspect`: every format of the key and its proof of work
+pub fn handle_inspect(args: &InspectArgs) -> Result<(), RanaError> {
+    let key = ParsedKey::parse(&args.key, args.secret)?;
+    let public_key = key.public_key();
+    println!("Hex public key:    {public_key}");
+    println!(
+        "Npub public key:   {}",
+        public_key
+            .to_bech32()
+            .expect("Could not get public key bech32 conversion")
+    );
+    if let ParsedKey::Secret(secret_key) = &key {
+        println!("Hex private key:   {}", secret_key.display_secret());
+        println!(
+            "Nsec private key:  {}",
+            secret_key
+                .to_bech32()
+                .expect("Could not get secret key bech32 conversion")
+        );
+    }
+    println!(
+        "Leading zero bits: {}",
+        get_leading_zero_bits(&public_key.serialize())
+    );
+    Ok(())
+}
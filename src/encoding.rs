@@ -0,0 +1,173 @@
+use crate::matcher::{BECH32_PREFIX, HEX_LEN};
+use crate::pattern::HEX_ALPHABET;
+
+/// Length of an npub: `npub1`, 52 characters of data and 6 of checksum
+pub const NPUB_LEN: usize = 63;
+// Characters of data after `npub1`, the 256 bits of the key and 4 of padding
+const NPUB_DATA_CHARS: usize = 52;
+
+/// Bech32 characters, in the order of the 5-bit values they encode
+pub const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const BECH32_GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// A prefix of the raw 32 bytes of a public key, compared with a mask so
+/// that it can end at any bit. Npub and hex prefixes compile to one of these,
+/// which avoids formatting every candidate as a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitPrefix {
+    mask: [u8; 32],
+    value: [u8; 32],
+    bits: usize,
+}
+
+impl BitPrefix {
+    /// Compile from a string of `0`s and `1`s
+    pub fn from_binary(prefix: &str) -> Option<Self> {
+        let bits = prefix
+            .chars()
+            .map(|c| match c {
+                '0' => Some(0),
+                '1' => Some(1),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()?;
+        Self::from_bits(&bits)
+    }
+
+    /// Compile from hexadecimal characters, 4 bits each, so odd lengths work
+    pub fn from_hex(prefix: &str) -> Option<Self> {
+        Self::from_groups(prefix, HEX_ALPHABET, 4)
+    }
+
+    /// Compile from the characters after `npub1`, 5 bits each. Returns `None`
+    /// when the prefix reaches into the checksum or cannot match any key.
+    pub fn from_npub(prefix: &str) -> Option<Self> {
+        // Even a checksum character of zeros would pass for padding
+        if prefix.chars().count() > NPUB_DATA_CHARS {
+            return None;
+        }
+        Self::from_groups(prefix, BECH32_CHARSET, 5)
+    }
+
+    fn from_groups(prefix: &str, alphabet: &str, group_bits: usize) -> Option<Self> {
+        let mut bits = Vec::with_capacity(prefix.len() * group_bits);
+        for c in prefix.chars() {
+            let group = alphabet.find(c)?;
+            for i in (0..group_bits).rev() {
+                bits.push(((group >> i) & 1) as u8);
+            }
+        }
+        Self::from_bits(&bits)
+    }
+
+    fn from_bits(bits: &[u8]) -> Option<Self> {
+        // The last npub character has 4 bits of zero padding after the key
+        if bits.iter().skip(256).any(|bit| *bit != 0) {
+            return None;
+        }
+        let mut prefix = BitPrefix {
+            mask: [0; 32],
+            value: [0; 32],
+            bits: bits.len().min(256),
+        };
+        for (i, bit) in bits.iter().take(256).enumerate() {
+            prefix.mask[i / 8] |= 0x80 >> (i % 8);
+            prefix.value[i / 8] |= bit << (7 - i % 8);
+        }
+        Some(prefix)
+    }
+
+    /// Number of bits the prefix fixes
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    #[inline]
+    pub fn matches(&self, key: &[u8; 32]) -> bool {
+        let bytes = self.bits.div_ceil(8);
+        key[..bytes]
+            .iter()
+            .zip(&self.mask[..bytes])
+            .zip(&self.value[..bytes])
+            .all(|((k, m), v)| k & m == *v)
+    }
+
+    /// Number of leading bits of the key that match the prefix
+    pub fn matching_bits(&self, key: &[u8; 32]) -> usize {
+        for (i, ((k, m), v)) in key.iter().zip(&self.mask).zip(&self.value).enumerate() {
+            let diff = (k & m) ^ v;
+            if diff != 0 {
+                return (i * 8 + diff.leading_zeros() as usize).min(self.bits);
+            }
+        }
+        self.bits
+    }
+}
+
+/// Encode the x-only public key as an npub without allocating
+pub fn encode_npub<'a>(key: &[u8; 32], buf: &'a mut [u8; NPUB_LEN]) -> &'a str {
+    let alphabet = BECH32_CHARSET.as_bytes();
+    buf[..BECH32_PREFIX.len()].copy_from_slice(BECH32_PREFIX.as_bytes());
+
+    // hrp expansion of "npub", followed by the data and the checksum
+    let mut checksum: u32 = 1;
+    for c in b"npub" {
+        checksum = polymod_step(checksum, c >> 5);
+    }
+    checksum = polymod_step(checksum, 0);
+    for c in b"npub" {
+        checksum = polymod_step(checksum, c & 31);
+    }
+
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+    let mut position = BECH32_PREFIX.len();
+    for byte in key {
+        accumulator = (accumulator << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            let group = ((accumulator >> bits) & 31) as u8;
+            checksum = polymod_step(checksum, group);
+            buf[position] = alphabet[group as usize];
+            position += 1;
+        }
+    }
+    let group = ((accumulator << (5 - bits)) & 31) as u8;
+    checksum = polymod_step(checksum, group);
+    buf[position] = alphabet[group as usize];
+    position += 1;
+
+    for _ in 0..6 {
+        checksum = polymod_step(checksum, 0);
+    }
+    checksum ^= 1;
+    for i in 0..6 {
+        buf[position + i] = alphabet[((checksum >> (5 * (5 - i))) & 31) as usize];
+    }
+
+    std::str::from_utf8(&buf[..]).expect("npub is ascii")
+}
+
+/// Encode the x-only public key as lowercase hex without allocating
+pub fn encode_hex<'a>(key: &[u8; 32], buf: &'a mut [u8; HEX_LEN]) -> &'a str {
+    let alphabet = HEX_ALPHABET.as_bytes();
+    for (i, byte) in key.iter().enumerate() {
+        buf[2 * i] = alphabet[(byte >> 4) as usize];
+        buf[2 * i + 1] = alphabet[(byte & 15) as usize];
+    }
+    std::str::from_utf8(&buf[..]).expect("hex is ascii")
+}
+
+#[inline]
+fn polymod_step(checksum: u32, value: u8) -> u32 {
+    let top = checksum >> 25;
+    let mut checksum = ((checksum & 0x1ffffff) << 5) ^ value as u32;
+    for (i, generator) in BECH32_GENERATOR.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            checksum ^= generator;
+        }
+    }
+    checksum
+}
@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// What is wrong with the arguments rana was given
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RanaError {
+    /// More than one of difficulty, vanity prefixes, infixes and patterns
+    MultipleRequirements,
+    /// A hex target has a character that is not hexadecimal. The position
+    /// starts at 1.
+    InvalidHex {
+        option: &'static str,
+        target: String,
+        position: usize,
+        character: char,
+    },
+    /// An npub target has a character that Bech32 does not use. The position
+    /// starts at 1.
+    InvalidBech32 {
+        option: &'static str,
+        target: String,
+        position: usize,
+        character: char,
+    },
+    /// A target that no public key is long enough to have
+    TargetTooLong {
+        option: &'static str,
+        target: String,
+        max: usize,
+    },
+    /// Two options that cannot be used together
+    Conflict {
+        option: &'static str,
+        other: &'static str,
+        reason: &'static str,
+    },
+    /// An option that only makes sense with another one
+    MissingOption {
+        option: &'static str,
+        requires: &'static str,
+    },
+    InvalidValue {
+        option: &'static str,
+        reason: String,
+    },
+    InvalidCores {
+        requested: usize,
+        available: usize,
+    },
+    InvalidMnemonic(String),
+    /// A mnemonic with a number of words that BIP-39 does not use
+    MnemonicWordCount(usize),
+    /// Words of a mnemonic that are not in the word list
+    UnknownWords(Vec<UnknownWord>),
+    /// A mnemonic whose checksum, in its last word, does not match
+    MnemonicChecksum,
+    StateFileExists(PathBuf),
+}
+
+/// A word of a mnemonic that is not in the word list. The position starts
+/// at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownWord {
+    pub position: usize,
+    pub word: String,
+    /// The closest words of the list, the closest first
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for RanaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RanaError::MultipleRequirements => write!(
+                f,
+                "You cannot specify more than one requirement. You should choose between difficulty or any of the vanity formats."
+            ),
+            RanaError::InvalidHex {
+                option,
+                target,
+                position,
+                character,
+            } => write!(
+                f,
+                "Invalid {option} '{target}': '{character}' at position {position} is not a hexadecimal character (0123456789abcdef)."
+            ),
+            RanaError::InvalidBech32 {
+                option,
+                target,
+                position,
+                character,
+            } => write!(
+                f,
+                "Invalid {option} '{target}': '{character}' at position {position} is not supported by Bech32 (023456789acdefghjklmnpqrstuvwxyz)."
+            ),
+            RanaError::TargetTooLong {
+                option,
+                target,
+                max,
+            } => write!(
+                f,
+                "The {option} '{target}' cannot be longer than {max} characters."
+            ),
+            RanaError::Conflict {
+                option,
+                other,
+                reason,
+            } => write!(f, "{option} cannot be used with {other}: {reason}."),
+            RanaError::MissingOption { option, requires } => {
+                write!(f, "{option} can only be used together with {requires}.")
+            }
+            RanaError::InvalidValue { option, reason } => write!(f, "Invalid {option}: {reason}."),
+            RanaError::InvalidCores {
+                requested: 0,
+                ..
+            } => write!(
+                f,
+                "There can be no proof of work if one does not do work (-c, --cores must be greater than 0)."
+            ),
+            RanaError::InvalidCores {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Your processor has {available} cores; cannot set -c, --cores to {requested}."
+            ),
+            RanaError::InvalidMnemonic(reason) => write!(f, "Invalid mnemonic: {reason}."),
+            RanaError::MnemonicWordCount(count) => write!(
+                f,
+                "Invalid mnemonic: it has {count} words instead of 12, 15, 18, 21 or 24."
+            ),
+            RanaError::UnknownWords(words) => {
+                write!(f, "Invalid mnemonic: these words are not in the word list:")?;
+                for word in words {
+                    write!(f, "\n  word {} '{}'", word.position, word.word)?;
+                    if !word.suggestions.is_empty() {
+                        write!(f, ", did you mean {}?", word.suggestions.join(", "))?;
+                    }
+                }
+                Ok(())
+            }
+            RanaError::MnemonicChecksum => write!(
+                f,
+                "Invalid mnemonic: the checksum does not match. A word is mistyped or the words are in the wrong order."
+            ),
+            RanaError::StateFileExists(path) => write!(
+                f,
+                "The state file {} already exists. Use --resume to continue its search.",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for RanaError {}
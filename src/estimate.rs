@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+/// How long mining takes for a target matched by a random key with
+/// `probability`, at `rate` keys per second
+pub struct Estimate {
+    pub probability: f64,
+    pub rate: f64,
+}
+
+impl Estimate {
+    /// Equivalent number of bits of proof of work
+    pub fn bits(&self) -> f64 {
+        -self.probability.log2()
+    }
+
+    pub fn expected_iterations(&self) -> f64 {
+        1.0 / self.probability
+    }
+
+    /// Iterations after which a match was found with the given confidence,
+    /// between 0 and 1. The number of tries follows a geometric distribution.
+    pub fn iterations_for(&self, confidence: f64) -> f64 {
+        (1.0 - confidence).ln() / (-self.probability).ln_1p()
+    }
+
+    /// Probability that a match was found after `iterations`
+    pub fn chance_after(&self, iterations: u64) -> f64 {
+        -(iterations as f64 * (-self.probability).ln_1p()).exp_m1()
+    }
+
+    /// Time after which a match was found with the given confidence, None
+    /// when it is out of reach
+    pub fn time_for(&self, confidence: f64) -> Option<Duration> {
+        let seconds = self.iterations_for(confidence) / self.rate;
+        Duration::try_from_secs_f64(seconds).ok()
+    }
+
+    pub fn print(&self) {
+        if self.probability <= 0.0 {
+            eprintln!("No public key can match this target");
+            return;
+        }
+        eprintln!(
+            "Searching for the equivalent of {:.1} bits, about {:.0} keys per match",
+            self.bits(),
+            self.expected_iterations()
+        );
+        eprintln!("Mining {:.0} keys per second", self.rate);
+        for confidence in [0.5, 0.9, 0.99] {
+            eprintln!(
+                "{:.0}% chance of a match within {}",
+                confidence * 100.0,
+                self.time_for(confidence)
+                    .map_or_else(|| "forever".to_string(), format_duration)
+            );
+        }
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    let (value, unit) = if seconds < 60.0 {
+        (seconds, "seconds")
+    } else if seconds < 3600.0 {
+        (seconds / 60.0, "minutes")
+    } else if seconds < 86400.0 {
+        (seconds / 3600.0, "hours")
+    } else if seconds < 86400.0 * 365.25 {
+        (seconds / 86400.0, "days")
+    } else {
+        (seconds / (86400.0 * 365.25), "years")
+    };
+    format!("{value:.1} {unit}")
+}
@@ -0,0 +1,270 @@
+//! NIP-13 proof of work for events: the `nonce` tag is changed until the id
+//! of the event has the requested number of leading zero bits.
+
+use std::fs;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nostr::hashes::sha256;
+use nostr::hashes::{Hash, HashEngine};
+use nostr::prelude::*;
+use nostr::secp256k1::schnorr::Signature;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{check_cores, EventArgs};
+use crate::matcher::LeadingZeroBitsMatcher;
+use crate::miner::{Miner, MiningEvent, MiningJob};
+use crate::signal;
+use crate::splitkey::parse_secret_key;
+use crate::utils::get_leading_zero_bits;
+
+// Nonces tried between checks for a result or an interruption
+const BATCH_SIZE: u64 = 4096;
+
+/// An unsigned event to mine, without an id. The public key can be left out
+/// when the event is signed, and `created_at` defaults to now.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EventTemplate {
+    pub pubkey: Option<XOnlyPublicKey>,
+    pub created_at: Option<u64>,
+    pub kind: u64,
+    #[serde(default)]
+    pub tags: Vec<Vec<String>>,
+    #[serde(default)]
+    pub content: String,
+}
+
+/// An event with proof of work, signed or not
+#[derive(Clone, Debug, Serialize)]
+pub struct MinedEvent {
+    pub id: String,
+    pub pubkey: XOnlyPublicKey,
+    pub created_at: u64,
+    pub kind: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sig: Option<Signature>,
+}
+
+impl MinedEvent {
+    pub fn sign(&mut self, keys: &Keys) -> Result<()> {
+        if keys.public_key() != self.pubkey {
+            return Err("The event was mined for another public key".into());
+        }
+        let message = Message::from_slice(EventId::from_hex(&self.id)?.as_bytes())?;
+        self.sig = Some(keys.sign_schnorr(&message)?);
+        Ok(())
+    }
+}
+
+/// Result of the proof of work of an event
+pub struct EventMining {
+    /// None when the mining was cancelled
+    pub event: Option<MinedEvent>,
+    pub iterations: u64,
+    pub elapsed: Duration,
+}
+
+/// Search the nonce giving the id of the event `difficulty` leading zero
+/// bits with `threads` threads. The target difficulty is committed in the
+/// `nonce` tag, which replaces any existing one. Setting `cancelled` stops
+/// the search.
+pub fn mine_event(
+    template: &EventTemplate,
+    pubkey: XOnlyPublicKey,
+    difficulty: u8,
+    threads: usize,
+    cancelled: &AtomicBool,
+) -> EventMining {
+    let start = Instant::now();
+    let created_at = template
+        .created_at
+        .unwrap_or_else(|| Timestamp::now().as_u64());
+    let mut tags = template.tags.clone();
+    tags.retain(|tag| tag.first().map(String::as_str) != Some("nonce"));
+
+    // The serialized event is `prefix`, the nonce, then `suffix`
+    let mut prefix = format!(
+        "[0,{},{created_at},{},[",
+        json_string(&pubkey.to_string()),
+        template.kind
+    );
+    for tag in &tags {
+        prefix += &serde_json::to_string(tag).expect("Tags can be serialized");
+        prefix += ",";
+    }
+    prefix += "[\"nonce\",\"";
+    let suffix = format!("\",\"{difficulty}\"]],{}]", json_string(&template.content));
+    let mut engine = sha256::Hash::engine();
+    engine.input(prefix.as_bytes());
+
+    let done = AtomicBool::new(false);
+    let iterations = AtomicU64::new(0);
+    let found = Mutex::new(None);
+    thread::scope(|scope| {
+        for index in 0..threads {
+            let (engine, suffix) = (&engine, &suffix);
+            let (done, iterations, found) = (&done, &iterations, &found);
+            scope.spawn(move || {
+                let mut nonce = index as u64;
+                let mut buffer = [0u8; 20];
+                loop {
+                    let mut tried = 0;
+                    while tried < BATCH_SIZE {
+                        tried += 1;
+                        let mut hasher = engine.clone();
+                        hasher.input(decimal(nonce, &mut buffer));
+                        hasher.input(suffix.as_bytes());
+                        let id = sha256::Hash::from_engine(hasher);
+                        if get_leading_zero_bits(&id[..]) >= difficulty {
+                            found
+                                .lock()
+                                .expect("No thread panics holding the lock")
+                                .get_or_insert((nonce, id));
+                            done.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                        nonce += threads as u64;
+                    }
+                    iterations.fetch_add(tried, Ordering::Relaxed);
+                    if done.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    let event = found
+        .into_inner()
+        .expect("No thread panicked")
+        .map(|(nonce, id)| {
+            tags.push(vec![
+                "nonce".to_string(),
+                nonce.to_string(),
+                difficulty.to_string(),
+            ]);
+            MinedEvent {
+                id: id.to_string(),
+                pubkey,
+                created_at,
+                kind: template.kind,
+                tags,
+                content: template.content.clone(),
+                sig: None,
+            }
+        });
+    EventMining {
+        event,
+        iterations: iterations.into_inner(),
+        elapsed: start.elapsed(),
+    }
+}
+
+fn json_string(value: &str) -> String {
+    serde_json::to_string(value).expect("Strings can be serialized")
+}
+
+// The decimal digits of `n`, without allocating
+fn decimal(mut n: u64, buffer: &mut [u8; 20]) -> &[u8] {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buffer[start..];
+        }
+    }
+}
+
+/// `rana event`: mine the proof of work of an event and write it to stdout
+pub fn handle_event(args: &EventArgs) -> Result<()> {
+    check_cores(args.num_cores)?;
+    let json = if args.event.as_os_str() == "-" {
+        let mut json = String::new();
+        io::stdin().read_to_string(&mut json)?;
+        json
+    } else {
+        fs::read_to_string(&args.event)
+            .map_err(|e| format!("Could not read {}: {e}", args.event.display()))?
+    };
+    let template: EventTemplate = serde_json::from_str(&json)?;
+
+    let keys = match (&args.secret_key, args.key_difficulty) {
+        (Some(secret_key), _) => Some(Keys::new(parse_secret_key(secret_key)?)),
+        (None, Some(difficulty)) => Some(mine_key(difficulty, args.num_cores)?),
+        (None, None) => None,
+    };
+    let pubkey = match (&keys, template.pubkey) {
+        (Some(keys), Some(pubkey)) if keys.public_key() != pubkey => {
+            return Err("The pubkey of the event is not the one of the signing key".into())
+        }
+        (Some(keys), _) => keys.public_key(),
+        (None, Some(pubkey)) => pubkey,
+        (None, None) => return Err("The event needs a pubkey when it is not signed".into()),
+    };
+
+    signal::handle_interrupts();
+    let cancelled = AtomicBool::new(false);
+    let mining = thread::scope(|scope| {
+        let mining = scope.spawn(|| {
+            mine_event(
+                &template,
+                pubkey,
+                args.difficulty,
+                args.num_cores,
+                &cancelled,
+            )
+        });
+        // Cancel the search on Ctrl-C, like the key miner
+        while !mining.is_finished() {
+            if signal::interrupted() {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        mining.join().expect("Event mining thread panicked")
+    });
+    let Some(mut event) = mining.event else {
+        return Err(format!("Interrupted after {} iterations", mining.iterations).into());
+    };
+    eprintln!(
+        "{} iterations in {:.1} seconds. Avg rate {:.0} hashes/second",
+        mining.iterations,
+        mining.elapsed.as_secs_f64(),
+        mining.iterations as f64 / mining.elapsed.as_secs_f64().max(0.001)
+    );
+    if let Some(keys) = &keys {
+        event.sign(keys)?;
+    }
+    println!("{}", serde_json::to_string(&event)?);
+    Ok(())
+}
+
+// A new key to sign the event with. Its private key is only shown on stderr,
+// to keep stdout for the event.
+fn mine_key(difficulty: u8, threads: usize) -> Result<Keys> {
+    let job = MiningJob::builder()
+        .matcher(LeadingZeroBitsMatcher::new(difficulty, false))
+        .threads(threads)
+        .max_matches(1)
+        .build();
+    let mut keys = None;
+    Miner::run(job, |event| {
+        if let MiningEvent::Found(found) = event {
+            keys = Some(found.keys);
+        }
+    });
+    let keys = keys.ok_or("Could not mine a key")?;
+    eprintln!(
+        "Signing with a new key:\nPublic key: {}\nPrivate key: {}",
+        keys.public_key().to_bech32()?,
+        keys.secret_key()?.to_bech32()?
+    );
+    Ok(keys)
+}
@@ -0,0 +1,516 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use nostr::bip39::{Language, Mnemonic};
+use nostr::bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use nostr::hashes::hmac::{Hmac, HmacEngine};
+use nostr::hashes::{sha256, sha512, Hash, HashEngine};
+use nostr::prelude::*;
+use nostr::secp256k1::PublicKey;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::compress512;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::mnemonic::{self, MAX_ACCOUNT};
+use crate::splitkey;
+
+/// Where the candidate keys come from
+#[derive(Clone, Debug, Default)]
+pub enum KeySource {
+    /// Random secret keys, one full scalar multiplication per candidate
+    #[default]
+    Random,
+    /// Walk the curve from a random secret key per thread by adding the
+    /// generator point, which is much cheaper than a scalar multiplication.
+    /// The secret key is only computed for the reported candidates.
+    Incremental,
+    /// Keys derived from a freshly generated mnemonic (NIP-06)
+    Mnemonic {
+        word_count: usize,
+        passphrase: String,
+        language: Language,
+    },
+    /// Split-key mining: walk from the public key of a customer, so that only
+    /// the tweak to add to their secret key is found, see [`crate::splitkey`]
+    SplitKey { base: XOnlyPublicKey },
+    /// The keys of a single mnemonic (NIP-06). The threads take turns over
+    /// the accounts or the address indices, so the key found can always be
+    /// restored from the mnemonic.
+    Derivation {
+        mnemonic: Mnemonic,
+        passphrase: String,
+        scan: DerivationScan,
+    },
+}
+
+/// Part of the NIP-06 path `m/44'/1237'/<account>'/0/<index>` that is scanned
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DerivationScan {
+    /// The accounts, with address index 0, which clients restore
+    Account,
+    /// The address indices of account 0, twice as fast to derive
+    Index,
+}
+
+impl KeySource {
+    /// Candidates of the worker thread `index` out of `threads`. With a seed,
+    /// every thread gets its own ChaCha20 stream, so the candidates of each
+    /// thread are always the same and a search can be replayed.
+    pub(crate) fn stream(
+        &self,
+        seed: Option<u64>,
+        index: usize,
+        threads: usize,
+    ) -> Box<dyn KeyStream> {
+        match seed {
+            Some(seed) => {
+                let mut rng = ChaCha20Rng::seed_from_u64(seed);
+                rng.set_stream(index as u64);
+                self.stream_with(rng, index, threads)
+            }
+            None => self.stream_with(rand::thread_rng(), index, threads),
+        }
+    }
+
+    fn stream_with<R: Rng + 'static>(
+        &self,
+        mut rng: R,
+        index: usize,
+        threads: usize,
+    ) -> Box<dyn KeyStream> {
+        match self {
+            KeySource::Random => Box::new(RandomStream::new(rng)),
+            KeySource::Incremental => Box::new(IncrementalStream::new(&mut rng, None)),
+            KeySource::SplitKey { base } => Box::new(IncrementalStream::new(
+                &mut rng,
+                Some(splitkey::base_point(base)),
+            )),
+            KeySource::Mnemonic {
+                word_count,
+                passphrase,
+                language,
+            } => Box::new(MnemonicStream::new(rng, *word_count, passphrase, *language)),
+            KeySource::Derivation {
+                mnemonic,
+                passphrase,
+                scan,
+            } => Box::new(DerivationStream::new(
+                mnemonic, passphrase, *scan, index, threads,
+            )),
+        }
+    }
+
+    /// Number of candidate public keys a single thread generates per second
+    pub fn benchmark(&self, duration: Duration) -> u64 {
+        let mut stream = self.stream(None, 0, 1);
+        let mut count: u64 = 0;
+        let now = Instant::now();
+        while now.elapsed() < duration && stream.next_public_key().is_some() {
+            count += 1;
+        }
+        (count as f64 / now.elapsed().as_secs_f64()) as u64
+    }
+}
+
+/// Candidates of a single worker thread
+pub(crate) trait KeyStream {
+    /// Move to the next candidate and return its public key, or None when
+    /// every candidate was tried
+    fn next_public_key(&mut self) -> Option<XOnlyPublicKey>;
+
+    /// Key pair (and mnemonic, if any) of the current candidate. Only called
+    /// for the candidates that get reported, so it may be slow.
+    fn keys(&self) -> (Keys, Option<Mnemonic>);
+
+    /// In split-key mining, the tweak that gives the current candidate when
+    /// added to the secret key of the customer
+    fn tweak(&self) -> Option<SecretKey> {
+        None
+    }
+
+    /// NIP-06 derivation path of the current candidate, when scanning the
+    /// derivations of a mnemonic
+    fn derivation_path(&self) -> Option<DerivationPath> {
+        None
+    }
+}
+
+struct RandomStream<R> {
+    rng: R,
+    keys: Option<Keys>,
+}
+
+impl<R: Rng> RandomStream<R> {
+    fn new(rng: R) -> Self {
+        RandomStream { rng, keys: None }
+    }
+}
+
+impl<R: Rng> KeyStream for RandomStream<R> {
+    fn next_public_key(&mut self) -> Option<XOnlyPublicKey> {
+        let keys = Keys::generate_without_keypair(&mut self.rng);
+        let public_key = keys.public_key();
+        self.keys = Some(keys);
+        Some(public_key)
+    }
+
+    fn keys(&self) -> (Keys, Option<Mnemonic>) {
+        (self.keys.clone().expect("No candidate generated yet"), None)
+    }
+}
+
+struct IncrementalStream {
+    start: SecretKey,
+    // Public key of the customer in split-key mining, whose secret key we
+    // don't know
+    base: Option<PublicKey>,
+    generator: PublicKey,
+    point: PublicKey,
+    // Number of times the generator was added to the start point
+    steps: u64,
+}
+
+impl IncrementalStream {
+    fn new<R: Rng>(rng: &mut R, base: Option<PublicKey>) -> Self {
+        let start = SecretKey::new(rng);
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let generator = PublicKey::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(&one).expect("One is a valid secret key"),
+        );
+        let mut point = PublicKey::from_secret_key(SECP256K1, &start);
+        if let Some(base) = base {
+            point = point.combine(&base).expect("Point at infinity");
+        }
+        IncrementalStream {
+            start,
+            base,
+            generator,
+            point,
+            steps: 0,
+        }
+    }
+
+    // The start secret plus the number of steps taken
+    fn current_secret(&self) -> SecretKey {
+        let mut tweak = [0u8; 32];
+        tweak[24..].copy_from_slice(&(self.steps - 1).to_be_bytes());
+        let tweak = Scalar::from_be_bytes(tweak).expect("Tweak is lower than the curve order");
+        self.start
+            .add_tweak(&tweak)
+            .expect("Secret key out of range")
+    }
+}
+
+impl KeyStream for IncrementalStream {
+    fn next_public_key(&mut self) -> Option<XOnlyPublicKey> {
+        if self.steps > 0 {
+            // Only fails when reaching the point at infinity, i.e. never
+            self.point = self
+                .point
+                .combine(&self.generator)
+                .expect("Point at infinity");
+        }
+        self.steps += 1;
+        Some(self.point.x_only_public_key().0)
+    }
+
+    fn keys(&self) -> (Keys, Option<Mnemonic>) {
+        match self.base {
+            Some(_) => (
+                Keys::from_public_key(self.point.x_only_public_key().0),
+                None,
+            ),
+            None => (Keys::new(self.current_secret()), None),
+        }
+    }
+
+    fn tweak(&self) -> Option<SecretKey> {
+        self.base.map(|_| self.current_secret())
+    }
+}
+
+// Rounds of PBKDF2-HMAC-SHA512 that turn a mnemonic into its seed (BIP-39)
+const PBKDF2_ROUNDS: usize = 2048;
+// Initial hash value of SHA-512 (FIPS 180-4)
+const SHA512_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+/// Keys of freshly generated mnemonics. The entropy goes straight to the
+/// sentence, the seed and the NIP-06 key, and the `Mnemonic` is only built
+/// for the reported candidates. The entropy is random rather than counted
+/// up, or the mnemonics of the keys found would give each other away.
+struct MnemonicStream<R> {
+    rng: R,
+    entropy: [u8; 32],
+    // 4 bytes of entropy for every 3 words
+    entropy_len: usize,
+    word_count: usize,
+    language: Language,
+    // "mnemonic" followed by the normalized passphrase
+    salt: Vec<u8>,
+    // The words of the current mnemonic, separated by spaces
+    sentence: Vec<u8>,
+    secret: Option<SecretKey>,
+}
+
+impl<R: Rng> MnemonicStream<R> {
+    fn new(rng: R, word_count: usize, passphrase: &str, language: Language) -> Self {
+        MnemonicStream {
+            rng,
+            entropy: [0; 32],
+            entropy_len: word_count * 4 / 3,
+            word_count,
+            language,
+            salt: format!("mnemonic{}", passphrase.nfkd()).into_bytes(),
+            sentence: Vec::with_capacity(256),
+            secret: None,
+        }
+    }
+
+    // Every word is 11 bits of the entropy followed by its checksum, the
+    // first bits of its SHA-256. The word lists are already normalized, and
+    // the seed of every language is computed with plain spaces.
+    fn write_sentence(&mut self) {
+        let entropy = &self.entropy[..self.entropy_len];
+        let checksum = sha256::Hash::hash(entropy)[0];
+        let words = self.language.word_list();
+        self.sentence.clear();
+        for word in 0..self.word_count {
+            let mut index = 0;
+            for bit in word * 11..(word + 1) * 11 {
+                let byte = entropy.get(bit / 8).copied().unwrap_or(checksum);
+                index = index << 1 | (byte >> (7 - bit % 8) & 1) as usize;
+            }
+            if word > 0 {
+                self.sentence.push(b' ');
+            }
+            self.sentence.extend_from_slice(words[index].as_bytes());
+        }
+    }
+}
+
+impl<R: Rng> KeyStream for MnemonicStream<R> {
+    fn next_public_key(&mut self) -> Option<XOnlyPublicKey> {
+        self.rng.fill_bytes(&mut self.entropy[..self.entropy_len]);
+        self.write_sentence();
+        let seed = bip39_seed(&self.sentence, &self.salt);
+        let secret = nip06_secret_key(&seed);
+        self.secret = Some(secret);
+        Some(secret.x_only_public_key(SECP256K1).0)
+    }
+
+    fn keys(&self) -> (Keys, Option<Mnemonic>) {
+        let secret = self.secret.expect("No candidate generated yet");
+        let mnemonic = Mnemonic::from_entropy_in(self.language, &self.entropy[..self.entropy_len])
+            .expect("Couldn't not generate mnemonic");
+        (Keys::new(secret), Some(mnemonic))
+    }
+}
+
+// PBKDF2-HMAC-SHA512 of the sentence. A single block gives the 64 bytes of
+// the seed. After the first one, every round hashes 64 bytes after a block of
+// key, so it compresses a single, already padded block from the states of the
+// inner and outer hashes after the key, which are computed once.
+fn bip39_seed(sentence: &[u8], salt: &[u8]) -> [u8; 64] {
+    // HMAC keys longer than a block are hashed first
+    let mut key = [0u8; 128];
+    if sentence.len() > key.len() {
+        key[..64].copy_from_slice(&sha512::Hash::hash(sentence)[..]);
+    } else {
+        key[..sentence.len()].copy_from_slice(sentence);
+    }
+    let keyed_state = |pad: u8| {
+        let mut state = SHA512_IV;
+        compress512(&mut state, &[key.map(|byte| byte ^ pad).into()]);
+        state
+    };
+    let (inner, outer) = (keyed_state(0x36), keyed_state(0x5c));
+
+    let mut engine = HmacEngine::<sha512::Hash>::new(sentence);
+    engine.input(salt);
+    engine.input(&1u32.to_be_bytes());
+    let mut round = Hmac::from_engine(engine).into_inner();
+    let mut seed = round;
+
+    // 64 bytes of message, the end marker and the length in bits of the key
+    // block and the message
+    let mut block = [0u8; 128];
+    block[64] = 0x80;
+    block[126..].copy_from_slice(&((128 + 64) * 8u16).to_be_bytes());
+    for _ in 1..PBKDF2_ROUNDS {
+        block[..64].copy_from_slice(&round);
+        let mut state = inner;
+        compress512(&mut state, &[block.into()]);
+        write_state(&state, &mut block[..64]);
+        let mut state = outer;
+        compress512(&mut state, &[block.into()]);
+        write_state(&state, &mut round);
+        seed.iter_mut()
+            .zip(round)
+            .for_each(|(seed, round)| *seed ^= round);
+    }
+    seed
+}
+
+fn write_state(state: &[u64; 8], hash: &mut [u8]) {
+    for (bytes, word) in hash.chunks_exact_mut(8).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+}
+
+// Secret key of the seed at m/44'/1237'/0'/0/0, the one of `Keys::from_mnemonic`
+fn nip06_secret_key(seed: &[u8; 64]) -> SecretKey {
+    let mut engine = HmacEngine::<sha512::Hash>::new(b"Bitcoin seed");
+    engine.input(seed);
+    let hmac = Hmac::<sha512::Hash>::from_engine(engine);
+    let mut secret = SecretKey::from_slice(&hmac[..32]).expect("Statistically impossible to hit");
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&hmac[32..]);
+    for child in [
+        ChildNumber::Hardened { index: 44 },
+        ChildNumber::Hardened { index: 1237 },
+        ChildNumber::Hardened { index: 0 },
+        ChildNumber::Normal { index: 0 },
+        ChildNumber::Normal { index: 0 },
+    ] {
+        (secret, chain_code) = child_key(&secret, &chain_code, child, None);
+    }
+    secret
+}
+
+struct DerivationStream {
+    mnemonic: Mnemonic,
+    scan: DerivationScan,
+    // Extended key the scanned indices are children of: m/44'/1237' for the
+    // accounts, m/44'/1237'/0'/0 for the address indices
+    secret: SecretKey,
+    chain_code: [u8; 32],
+    public_key: PublicKey,
+    next: u64,
+    step: u64,
+    current: Option<(u32, SecretKey)>,
+}
+
+impl DerivationStream {
+    fn new(
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        scan: DerivationScan,
+        index: usize,
+        threads: usize,
+    ) -> Self {
+        let path = match scan {
+            DerivationScan::Account => "m/44'/1237'",
+            DerivationScan::Index => "m/44'/1237'/0'/0",
+        };
+        let parent = mnemonic::master_key(mnemonic, passphrase)
+            .derive_priv(
+                SECP256K1,
+                &DerivationPath::from_str(path).expect("Valid derivation path"),
+            )
+            .expect("Valid derivation path");
+        DerivationStream {
+            mnemonic: mnemonic.clone(),
+            scan,
+            secret: parent.private_key,
+            chain_code: *parent.chain_code.as_bytes(),
+            public_key: PublicKey::from_secret_key(SECP256K1, &parent.private_key),
+            next: index as u64,
+            step: threads as u64,
+            current: None,
+        }
+    }
+}
+
+impl KeyStream for DerivationStream {
+    fn next_public_key(&mut self) -> Option<XOnlyPublicKey> {
+        if self.next > MAX_ACCOUNT as u64 {
+            return None;
+        }
+        let index = self.next as u32;
+        self.next += self.step;
+        let secret = match self.scan {
+            DerivationScan::Account => {
+                let hardened = ChildNumber::Hardened { index };
+                let (account, chain_code) =
+                    child_key(&self.secret, &self.chain_code, hardened, None);
+                let zero = ChildNumber::Normal { index: 0 };
+                let (change, chain_code) = child_key(&account, &chain_code, zero, None);
+                child_key(&change, &chain_code, zero, None).0
+            }
+            DerivationScan::Index => {
+                let normal = ChildNumber::Normal { index };
+                child_key(
+                    &self.secret,
+                    &self.chain_code,
+                    normal,
+                    Some(&self.public_key),
+                )
+                .0
+            }
+        };
+        self.current = Some((index, secret));
+        Some(secret.x_only_public_key(SECP256K1).0)
+    }
+
+    fn keys(&self) -> (Keys, Option<Mnemonic>) {
+        let (_, secret) = self.current.expect("No candidate generated yet");
+        (Keys::new(secret), Some(self.mnemonic.clone()))
+    }
+
+    fn derivation_path(&self) -> Option<DerivationPath> {
+        let (index, _) = self.current?;
+        let path = match self.scan {
+            DerivationScan::Account => mnemonic::derivation_path(index, 0),
+            DerivationScan::Index => mnemonic::derivation_path(0, index),
+        };
+        Some(path.expect("Indices are checked against the maximum"))
+    }
+}
+
+// BIP-32 derivation of a child secret key and its chain code. Normal children
+// hash the public key of their parent, which can be given when it is known,
+// to save a scalar multiplication.
+fn child_key(
+    secret: &SecretKey,
+    chain_code: &[u8; 32],
+    child: ChildNumber,
+    public_key: Option<&PublicKey>,
+) -> (SecretKey, [u8; 32]) {
+    let mut engine = HmacEngine::<sha512::Hash>::new(chain_code);
+    match child {
+        ChildNumber::Normal { .. } => {
+            let public_key = match public_key {
+                Some(public_key) => *public_key,
+                None => PublicKey::from_secret_key(SECP256K1, secret),
+            };
+            engine.input(&public_key.serialize());
+        }
+        ChildNumber::Hardened { .. } => {
+            engine.input(&[0]);
+            engine.input(&secret.secret_bytes());
+        }
+    }
+    engine.input(&u32::from(child).to_be_bytes());
+    let hmac = Hmac::<sha512::Hash>::from_engine(engine);
+    let tweak = Scalar::from_be_bytes(hmac[..32].try_into().expect("32 bytes"))
+        .expect("Statistically impossible to hit");
+    let child_secret = secret
+        .add_tweak(&tweak)
+        .expect("Statistically impossible to hit");
+    let mut child_chain_code = [0u8; 32];
+    child_chain_code.copy_from_slice(&hmac[32..]);
+    (child_secret, child_chain_code)
+}
@@ -1,4 +1,20 @@
+pub mod checkpoint;
 pub mod cli;
+pub mod convert;
+pub mod encoding;
+pub mod error;
+pub mod estimate;
+pub mod event;
+pub mod keysource;
+pub mod matcher;
+pub mod miner;
 pub mod mnemonic;
+pub mod nip49;
+pub mod output;
+pub mod pattern;
+pub mod progress;
+pub mod prompt;
+pub mod signal;
+pub mod splitkey;
 pub mod tests;
 pub mod utils;
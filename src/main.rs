@@ -1,127 +1,167 @@
 use colored::Colorize;
 use std::cmp::max;
-use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use clap::Parser;
-use nostr::bip39::Mnemonic;
 use nostr::prelude::*;
+use rana::checkpoint::{Checkpoint, CHECKPOINT_INTERVAL};
 use rana::cli::*;
-use rana::mnemonic::handle_mnemonic;
-use rana::utils::{benchmark_cores, get_leading_zero_bits, print_divider, print_keys, print_qr};
-
-const DIFFICULTY_DEFAULT: u8 = 10;
-const BECH32_PREFIX: &str = "npub1";
-
-fn calculate_string_similarity(target: &str, candidate: &str) -> f64 {
-    // Get the shorter length of the two strings to avoid index out of bounds
-    let min_len = std::cmp::min(target.len(), candidate.len());
-
-    // If either string is empty, return 0
-    if min_len == 0 {
-        return 0.0;
+use rana::convert::{handle_convert, handle_inspect};
+use rana::error::RanaError;
+use rana::estimate::Estimate;
+use rana::event;
+use rana::matcher::{
+    HexContainsMatcher, HexPrefixMatcher, LeadingZeroBitsMatcher, Matcher, NpubContainsMatcher,
+    NpubPrefixMatcher, NpubPrefixSuffixMatcher, NpubSuffixMatcher,
+};
+use rana::miner::{
+    benchmark, benchmark_scaling, DerivationScan, KeySource, Miner, MiningEvent, MiningJob,
+    MiningStats, DIFFICULTY_DEFAULT,
+};
+use rana::mnemonic::{generate_mnemonic, handle_mnemonic, parse_mnemonic, restore};
+use rana::nip49::Encryption;
+use rana::output::{KeyFile, Output};
+use rana::pattern::{PatternFormat, PatternMatcher};
+use rana::progress::Progress;
+use rana::prompt;
+use rana::signal;
+use rana::splitkey;
+
+/// Exit code when the arguments are invalid, like for clap's own errors
+const EXIT_INVALID_ARGS: i32 = 2;
+/// Exit code when stopping before the requested keys were found
+const EXIT_GAVE_UP: i32 = 3;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{} {e}", "Error:".red());
+        let code = if e.is::<RanaError>() {
+            EXIT_INVALID_ARGS
+        } else {
+            1
+        };
+        std::process::exit(code);
     }
-
-    // Count matching characters at the start of the string
-    let matching = target
-        .chars()
-        .take(min_len)
-        .zip(candidate.chars().take(min_len))
-        .take_while(|(a, b)| a == b)
-        .count();
-
-    // Calculate similarity as a percentage
-    (matching as f64 / target.len() as f64) * 100.0
 }
 
-#[derive(Clone)]
-struct BestMatch {
-    npub: String,
-    similarity: f64,
-    keys: Keys,
-    mnemonic: Option<Mnemonic>,
+fn run() -> Result<()> {
+    // Parse CLI arguments
+    let cli = CLIArgs::parse();
+
+    match cli.command {
+        Some(Command::Mine(args)) => mine(args),
+        Some(Command::Mnemonic(command)) => handle_mnemonic(&command),
+        Some(Command::Convert(args)) => Ok(handle_convert(&args)?),
+        Some(Command::Inspect(args)) => Ok(handle_inspect(&args)?),
+        Some(Command::Combine(args)) => splitkey::handle_combine(&args),
+        Some(Command::Event(args)) => event::handle_event(&args),
+        None => run_deprecated(cli.mine, cli.mnemonic),
+    }
 }
 
-impl BestMatch {
-    fn new() -> Self {
-        BestMatch {
-            npub: String::new(),
-            similarity: 0.0,
-            keys: Keys::generate(), // Generate a default key
-            mnemonic: None,
+// The flat command line of the previous versions: mining options, or -r to
+// restore a mnemonic
+fn run_deprecated(args: MineArgs, mnemonic: Option<String>) -> Result<()> {
+    let Some(mnemonic) = mnemonic else {
+        eprintln!(
+            "{}",
+            "Mining without a subcommand is deprecated, use `rana mine`.".yellow()
+        );
+        return mine(args);
+    };
+    eprintln!(
+        "{}",
+        "-r, --restore is deprecated, use `rana mnemonic restore`.".yellow()
+    );
+    if args.word_count > 0 {
+        return Err(RanaError::Conflict {
+            option: "-r, --restore",
+            other: "-g, --generate",
+            reason: "use `rana mnemonic generate` to generate a new mnemonic",
         }
+        .into());
     }
+    restore(&MnemonicRestoreArgs {
+        mnemonic: Some(mnemonic),
+        language: args.language,
+        passphrase: args.mnemonic_passphrase,
+        accounts: Default::default(),
+    })
 }
 
-fn main() -> Result<()> {
-    // Parse CLI arguments
-    let parsed_args = CLIArgs::parse();
+fn mine(mut parsed_args: MineArgs) -> Result<()> {
+    // Continue the search of the state file
+    let resumed = match parsed_args.state_file.clone() {
+        Some(path) if parsed_args.resume => {
+            let checkpoint = Checkpoint::load(&path)?;
+            checkpoint.resume(&mut parsed_args)?;
+            eprintln!(
+                "Resuming the search of {} after {} iterations",
+                path.display(),
+                checkpoint.iterations
+            );
+            Some(checkpoint)
+        }
+        _ => None,
+    };
 
-    // Handle mnemonic part if arguments is set
-    if !parsed_args.mnemonic.is_empty() {
-        handle_mnemonic(&parsed_args);
+    if parsed_args.scan_mnemonic.as_deref() == Some("-") {
+        parsed_args.scan_mnemonic = Some(prompt::read_secret("Mnemonic: ")?);
     }
 
+    //-- Calculate pow difficulty and initialize
+    check_args(&parsed_args)?;
+
     let mut difficulty: u8 = parsed_args.difficulty;
     let no_scaling: bool = parsed_args.no_scaling;
-    let vanity_prefix: String = parsed_args.vanity_prefix;
-    let mut vanity_npub_prefixes: Vec<String> = Vec::new();
-    let mut vanity_npub_suffixes: Vec<String> = Vec::new();
+    let vanity_prefix: String = parsed_args.vanity_prefix.clone();
+    let vanity_npub_prefixes: Vec<String> = parsed_args.vanity_npub_prefixes();
+    let vanity_npub_suffixes: Vec<String> = parsed_args.vanity_npub_suffixes();
+    let vanity_npub_contains: Vec<String> = parsed_args.vanity_npub_contains();
+    let vanity_contains: Vec<String> = parsed_args.vanity_contains();
     let num_cores: usize = parsed_args.num_cores;
     let qr: bool = parsed_args.qr;
     let verbose_output: bool = parsed_args.verbose_output;
-    let best_match = Arc::new(Mutex::new(BestMatch::new()));
-
-    for vanity_npub_pre in parsed_args.vanity_npub_prefixes_raw_input.split(',') {
-        if !vanity_npub_pre.is_empty() {
-            vanity_npub_prefixes.push(vanity_npub_pre.to_string())
-        }
-    }
-    for vanity_npub_post in parsed_args.vanity_npub_suffixes_raw_input.split(',') {
-        if !vanity_npub_post.is_empty() {
-            vanity_npub_suffixes.push(vanity_npub_post.to_string())
-        }
-    }
-
-    //-- Calculate pow difficulty and initialize
-    check_args(
-        difficulty,
-        vanity_prefix.as_str(),
-        &vanity_npub_prefixes,
-        &vanity_npub_suffixes,
-        num_cores,
-    );
 
     // initially the same as difficulty
     let mut pow_difficulty: u8 = difficulty;
+    let matcher: Arc<dyn Matcher>;
+    // the startup benchmark must not raise the difficulty of a scaling matcher
+    let mut benchmark_matcher: Option<Arc<dyn Matcher>> = None;
 
     if !vanity_prefix.is_empty() {
-        // set pow difficulty as the length of the prefix translated to bits
-        pow_difficulty = (vanity_prefix.len() * 4) as u8;
-        println!(
-            "Started mining process for vanity hex prefix: '{vanity_prefix}' (estimated pow: {pow_difficulty})"
-        );
+        matcher = Arc::new(HexPrefixMatcher::new(&vanity_prefix));
+    } else if !parsed_args.npub_pattern.is_empty() || !parsed_args.hex_pattern.is_empty() {
+        matcher = if !parsed_args.npub_pattern.is_empty() {
+            Arc::new(PatternMatcher::new(
+                &parsed_args.npub_pattern,
+                PatternFormat::Npub,
+            )?)
+        } else {
+            Arc::new(PatternMatcher::new(
+                &parsed_args.hex_pattern,
+                PatternFormat::Hex,
+            )?)
+        };
+    } else if !vanity_npub_contains.is_empty() {
+        matcher = Arc::new(NpubContainsMatcher::new(
+            vanity_npub_contains,
+            parsed_args.within,
+        ));
+    } else if !vanity_contains.is_empty() {
+        matcher = Arc::new(HexContainsMatcher::new(vanity_contains, parsed_args.within));
     } else if !vanity_npub_prefixes.is_empty() && !vanity_npub_suffixes.is_empty() {
-        // set pow difficulty as the length of the first prefix + first suffix translated to bits
-        pow_difficulty =
-            ((vanity_npub_prefixes[0].len() * 4) + (vanity_npub_suffixes[0].len() * 4)) as u8;
-        println!(
-            "Started mining process for vanity bech32 prefix[es]: 'npub1{vanity_npub_prefixes:?}' and suffix[es]: '...{vanity_npub_suffixes:?}' (estimated pow: {pow_difficulty})"
-        );
+        matcher = Arc::new(NpubPrefixSuffixMatcher::new(
+            vanity_npub_prefixes,
+            vanity_npub_suffixes,
+        ));
     } else if !vanity_npub_prefixes.is_empty() {
-        // set pow difficulty as the length of the first prefix translated to bits
-        pow_difficulty = (vanity_npub_prefixes[0].len() * 4) as u8;
-        println!(
-            "Started mining process for vanity bech32 prefix[es]: 'npub1{vanity_npub_prefixes:?}' (estimated pow: {pow_difficulty})"
-        );
+        matcher = Arc::new(NpubPrefixMatcher::new(vanity_npub_prefixes));
     } else if !vanity_npub_suffixes.is_empty() {
-        // set pow difficulty as the length of the first suffix translated to bits
-        pow_difficulty = (vanity_npub_suffixes[0].len() * 4) as u8;
-        println!(
-            "Started mining process for vanity bech32 suffix[es]: '...{vanity_npub_suffixes:?}' (estimated pow: {pow_difficulty})"
-        );
+        matcher = Arc::new(NpubSuffixMatcher::new(vanity_npub_suffixes));
     } else {
         // Defaults to using difficulty
 
@@ -131,217 +171,264 @@ fn main() -> Result<()> {
             pow_difficulty = difficulty;
         }
 
-        println!(
+        // carry on from the difficulty the scaling reached
+        let target_difficulty = pow_difficulty;
+        if let Some(checkpoint) = &resumed {
+            pow_difficulty = pow_difficulty.max(checkpoint.best_difficulty);
+        }
+
+        eprintln!(
             "Started mining process with a difficulty of: {difficulty} (pow: {pow_difficulty})"
         );
+        matcher = Arc::new(
+            LeadingZeroBitsMatcher::new(target_difficulty, !no_scaling).resume_from(pow_difficulty),
+        );
+        benchmark_matcher = Some(Arc::new(LeadingZeroBitsMatcher::new(pow_difficulty, false)));
+    }
+    if benchmark_matcher.is_none() {
+        eprintln!(
+            "Started mining process for {} (estimated pow: {:.1})",
+            matcher.description(),
+            matcher
+                .probability()
+                .map_or(0.0, |probability| -probability.log2())
+        );
     }
 
-    println!("Difficulty scaling: {}", !no_scaling);
+    eprintln!("Difficulty scaling: {}", !no_scaling);
 
-    // benchmark cores
-    if !vanity_npub_prefixes.is_empty() || !vanity_npub_suffixes.is_empty() {
-        println!("Benchmarking of cores disabled for vanity npub key upon proper calculation.");
-    } else {
-        benchmark_cores(num_cores, pow_difficulty);
+    let key_source = key_source(&parsed_args);
+
+    let mut job_builder = MiningJob::builder()
+        .shared_matcher(matcher)
+        .threads(num_cores)
+        .key_source(key_source)
+        .report_misses(verbose_output);
+    if let Some(seed) = parsed_args.seed {
+        eprintln!(
+            "{}",
+            "Keys are generated from a seed, anyone knowing it can find them again. Do not use them!"
+                .red()
+        );
+        job_builder = job_builder.seed(seed);
+    }
+    if let Some(count) = parsed_args.count {
+        job_builder = job_builder.max_matches(count);
+    }
+    if let Some(timeout) = parsed_args.timeout {
+        job_builder = job_builder.timeout(timeout);
+    }
+    if let Some(max_iterations) = parsed_args.max_iterations {
+        job_builder = job_builder.max_iterations(max_iterations);
     }
+    if let Some(checkpoint) = &resumed {
+        job_builder = job_builder.resume(checkpoint.stats()?);
+    }
+    let job = job_builder.build();
 
-    // Loop: generate public keys until desired public key is reached
-    let now = Instant::now();
-
-    println!("Mining using {num_cores} cores...");
-
-    // thread safe variables
-    let best_diff = Arc::new(AtomicU8::new(pow_difficulty));
-    let vanity_ts = Arc::new(vanity_prefix);
-    let vanity_npubs_pre_ts = Arc::new(vanity_npub_prefixes);
-    let vanity_npubs_post_ts = Arc::new(vanity_npub_suffixes);
-    let iterations = Arc::new(AtomicU64::new(0));
-
-    // start a thread for each core for calculations
-    for _ in 0..num_cores {
-        let best_diff = best_diff.clone();
-        let vanity_ts = vanity_ts.clone();
-        let vanity_npubs_pre_ts = vanity_npubs_pre_ts.clone();
-        let vanity_npubs_post_ts = vanity_npubs_post_ts.clone();
-        let passphrase = Arc::new(parsed_args.mnemonic_passphrase.clone());
-        let iterations = iterations.clone();
-        let best_match = best_match.clone();
-
-        thread::spawn(move || {
-            let mut rng = rand::thread_rng();
-
-            let mut keys;
-            let mut mnemonic;
-
-            loop {
-                let mut uses_mnemonic: Option<Mnemonic> = None;
-                iterations.fetch_add(1, Ordering::Relaxed);
-
-                // Use mnemonics to generate key pair
-                if parsed_args.word_count > 0 {
-                    mnemonic = Keys::generate_mnemonic(parsed_args.word_count)
-                        .expect("Couldn't not generate mnemonic");
-
-                    keys = Keys::from_mnemonic(mnemonic.to_string(), Some(passphrase.to_string()))
-                        .expect("Error generating keys from mnemonic");
-                    uses_mnemonic = Some(mnemonic);
-                } else {
-                    keys = Keys::generate_without_keypair(&mut rng);
-                }
+    if parsed_args.benchmark {
+        benchmark_key_sources(&parsed_args);
+        benchmark_threads(&job);
+        return Ok(());
+    }
 
-                let mut leading_zeroes: u8 = 0;
-                let mut vanity_npub: String = String::new();
-
-                // check pubkey validity depending on arg settings
-                let mut is_valid_pubkey: bool = false;
-
-                if !vanity_ts.is_empty() {
-                    // hex vanity search
-                    is_valid_pubkey = keys
-                        .public_key()
-                        .to_string()
-                        .starts_with(vanity_ts.as_str());
-                } else if !vanity_npubs_pre_ts.is_empty() || !vanity_npubs_post_ts.is_empty() {
-                    // bech32 vanity search
-                    let bech_key: String = keys.public_key().to_bech32().unwrap();
-
-                    if !vanity_npubs_pre_ts.is_empty() && !vanity_npubs_post_ts.is_empty() {
-                        for cur_vanity_npub_pre in vanity_npubs_pre_ts.iter() {
-                            let current_prefix = bech_key.strip_prefix(BECH32_PREFIX).unwrap_or("");
-                            let similarity =
-                                calculate_string_similarity(cur_vanity_npub_pre, current_prefix);
-
-                            let mut best_match_guard = best_match.lock().unwrap();
-                            if similarity > best_match_guard.similarity {
-                                best_match_guard.similarity = similarity;
-                                best_match_guard.npub = current_prefix.to_string();
-                                best_match_guard.keys = keys.clone();
-                                best_match_guard.mnemonic = uses_mnemonic.clone();
-
-                                // Check for any match above 75% but less than 100%
-                                if (75.0..100.0).contains(&similarity) {
-                                    println!("{}", print_divider(30).bright_yellow());
-                                    println!("Target:  {}", cur_vanity_npub_pre);
-                                    println!("Found match with {:.2}% similarity:", similarity);
-                                    print_keys(
-                                        &keys,
-                                        current_prefix.to_string(),
-                                        0,
-                                        uses_mnemonic.clone(),
-                                    )
-                                    .unwrap();
-                                    std::io::Write::flush(&mut std::io::stdout())
-                                        .expect("Failed to flush stdout");
-                                }
-
-                                // Handle exact match (100% similarity)
-                                if similarity == 100.0 {
-                                    is_valid_pubkey = true;
-                                    vanity_npub = cur_vanity_npub_pre.clone();
-                                    break;
-                                }
-                            }
-                        }
-                    } else if !vanity_npubs_pre_ts.is_empty() {
-                        for cur_vanity_npub_pre in vanity_npubs_pre_ts.iter() {
-                            let current_prefix = bech_key.strip_prefix(BECH32_PREFIX).unwrap_or("");
-                            let similarity =
-                                calculate_string_similarity(cur_vanity_npub_pre, current_prefix);
-
-                            let mut best_match_guard = best_match.lock().unwrap();
-                            if similarity > best_match_guard.similarity {
-                                best_match_guard.similarity = similarity;
-                                best_match_guard.npub = current_prefix.to_string();
-                                best_match_guard.keys = keys.clone();
-                                best_match_guard.mnemonic = uses_mnemonic.clone();
-
-                                // Check for any match above 75% but less than 100%
-                                if similarity >= 75.0 && similarity < 100.0 {
-                                    println!("{}", print_divider(30).bright_yellow());
-                                    println!("Target:  {}", cur_vanity_npub_pre);
-                                    println!("Found match with {:.2}% similarity:", similarity);
-                                    print_keys(
-                                        &keys,
-                                        current_prefix.to_string(),
-                                        0,
-                                        uses_mnemonic.clone(),
-                                    )
-                                    .unwrap();
-                                    std::io::Write::flush(&mut std::io::stdout())
-                                        .expect("Failed to flush stdout");
-                                }
-
-                                // Handle exact match (100% similarity)
-                                if similarity == 100.0 {
-                                    is_valid_pubkey = true;
-                                    vanity_npub = cur_vanity_npub_pre.clone();
-                                    break;
-                                }
-                            }
-                        }
-                    } else {
-                        for cur_vanity_npub in vanity_npubs_post_ts.iter() {
-                            is_valid_pubkey = bech_key.ends_with(cur_vanity_npub.as_str());
-
-                            if is_valid_pubkey {
-                                vanity_npub = cur_vanity_npub.clone();
-                                break;
-                            }
-                        }
-                    }
-                } else {
-                    // difficulty search
-                    leading_zeroes = get_leading_zero_bits(&keys.public_key().serialize());
-                    is_valid_pubkey = leading_zeroes > best_diff.load(Ordering::Relaxed);
-                    if is_valid_pubkey && !no_scaling {
-                        // update difficulty only if it was set in the first place
-                        if best_diff.load(Ordering::Relaxed) > 0 {
-                            best_diff
-                                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |_| {
-                                    Some(leading_zeroes)
-                                })
-                                .unwrap();
-                        }
-                    }
-                }
+    let mut output = Output::new(parsed_args.output_format, qr);
+    if let Some(path) = &parsed_args.out {
+        output = output.with_key_file(KeyFile::open(path)?);
+    }
+    if let Some(password) = &parsed_args.ncryptsec_password {
+        output = output.with_encryption(Encryption {
+            password: password.clone(),
+            log_n: parsed_args.ncryptsec_log_n,
+            key_security: parsed_args.key_security,
+        });
+    }
+
+    // estimate the mining time from the speed of the actual search
+    if let Some(probability) = job.matcher.probability() {
+        eprintln!("Benchmarking the search for 2 seconds...");
+        let mut benchmark_job = job.clone();
+        benchmark_job.matcher = benchmark_matcher.unwrap_or_else(|| job.matcher.clone());
+        let estimate = Estimate {
+            probability,
+            rate: benchmark(benchmark_job, Duration::from_secs(2)),
+        };
+        estimate.print();
+    }
 
-                // if one of the required conditions is satisfied
-                let shared_output = Arc::new(Mutex::new(std::io::stdout()));
-                if is_valid_pubkey {
-                    let _guard = shared_output.lock().unwrap();
-                    println!("{}", print_divider(30).bright_cyan());
-                    println!("Found exact match!");
-                    print_keys(&keys, vanity_npub, leading_zeroes, uses_mnemonic).unwrap();
-                    let iterations = iterations.load(Ordering::Relaxed);
-                    let iter_string = format!("{iterations}");
-                    let l = iter_string.len();
-                    let f = iter_string.chars().next().unwrap();
-                    println!(
-                        "{} iterations (about {}x10^{} hashes) in {} seconds. Avg rate {} hashes/second",
-                        iterations,
-                        f,
-                        l - 1,
-                        now.elapsed().as_secs(),
-                        iterations / max(1, now.elapsed().as_secs())
-                    );
-                    if qr {
-                        print_qr(keys.secret_key().unwrap()).unwrap();
+    eprintln!("Mining using {num_cores} cores...");
+
+    let mut checkpoint = parsed_args
+        .state_file
+        .as_ref()
+        .map(|_| resumed.unwrap_or_else(|| Checkpoint::new(&parsed_args, pow_difficulty)));
+    let mut last_checkpoint = Instant::now();
+
+    let mut progress = parsed_args
+        .progress
+        .map(|interval| Progress::new(interval, job.matcher.clone(), num_cores));
+
+    signal::handle_interrupts();
+    let miner = Miner::start(job);
+    loop {
+        if miner.stop_if_interrupted() {
+            eprintln!("\nInterrupted, stopping the workers...");
+        }
+        match miner.events().recv_timeout(Duration::from_millis(200)) {
+            Ok(MiningEvent::Found(found)) => {
+                if let Some(checkpoint) = &mut checkpoint {
+                    if !no_scaling {
+                        checkpoint.best_difficulty =
+                            checkpoint.best_difficulty.max(found.leading_zero_bits);
                     }
-                    std::io::Write::flush(&mut std::io::stdout()).expect("Failed to flush stdout");
-                } else if verbose_output {
-                    let non_matching_key = keys.public_key().to_string();
-                    print!(
-                        "Non-matching public key generated: {}\r",
-                        non_matching_key.red()
-                    );
-                    std::io::Write::flush(&mut std::io::stdout()).expect("Failed to flush stdout");
                 }
+                if let Some(progress) = &mut progress {
+                    progress.found(&found);
+                }
+                output.found(&found)
             }
-        });
+            Ok(MiningEvent::NearMiss(found)) => output.near_miss(&found),
+            Ok(MiningEvent::Miss(public_key)) => {
+                eprint!(
+                    "Non-matching public key generated: {}\r",
+                    public_key.to_string().red()
+                );
+                std::io::Write::flush(&mut std::io::stderr()).expect("Failed to flush stderr");
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if let Some(progress) = &mut progress {
+            progress.update(&miner, &mut output);
+        }
+        if let (Some(checkpoint), Some(path)) = (&mut checkpoint, &parsed_args.state_file) {
+            if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+                save_checkpoint(checkpoint, path, &miner.stats());
+                last_checkpoint = Instant::now();
+            }
+        }
+    }
+    let stats = miner.join();
+    if let (Some(checkpoint), Some(path)) = (&mut checkpoint, &parsed_args.state_file) {
+        save_checkpoint(checkpoint, path, &stats);
+    }
+    output.finish(&stats);
+
+    // Exit codes: 0 when the requested keys were found, 3 when giving up
+    // before that (timeout, iteration limit or interrupted)
+    let found = match parsed_args.count {
+        Some(count) => stats.matches >= count,
+        None => stats.matches > 0,
+    };
+    if !found {
+        std::process::exit(EXIT_GAVE_UP);
     }
 
-    // put main thread to sleep
-    loop {
-        thread::sleep(Duration::from_secs(3600));
+    Ok(())
+}
+
+fn save_checkpoint(checkpoint: &mut Checkpoint, path: &Path, stats: &MiningStats) {
+    checkpoint.update(stats);
+    if let Err(e) = checkpoint.save(path) {
+        eprintln!("Could not save the state file {}: {e}", path.display());
+    }
+}
+
+fn key_source(parsed_args: &MineArgs) -> KeySource {
+    let language = parsed_args.language.unwrap_or_default().into();
+    if let Some(scan) = parsed_args.derivation_scan() {
+        let mnemonic = match &parsed_args.scan_mnemonic {
+            Some(mnemonic) => parse_mnemonic(mnemonic, parsed_args.language.map(Into::into))
+                .expect("Checked with the arguments"),
+            None => generate_mnemonic(parsed_args.word_count, language),
+        };
+        KeySource::Derivation {
+            mnemonic,
+            passphrase: parsed_args.mnemonic_passphrase.clone(),
+            scan,
+        }
+    } else if parsed_args.word_count > 0 {
+        KeySource::Mnemonic {
+            word_count: parsed_args.word_count,
+            passphrase: parsed_args.mnemonic_passphrase.clone(),
+            language,
+        }
+    } else if let Some(split_key) = &parsed_args.split_key {
+        KeySource::SplitKey {
+            base: splitkey::parse_public_key(split_key).expect("Checked with the arguments"),
+        }
+    } else if parsed_args.incremental {
+        KeySource::Incremental
+    } else {
+        KeySource::Random
+    }
+}
+
+fn benchmark_key_sources(parsed_args: &MineArgs) {
+    // Keys from mnemonics are much slower, but can be restored from a backup
+    let word_count = match parsed_args.word_count {
+        0 => 12,
+        word_count => word_count,
+    };
+    let mut key_sources = vec![
+        ("random keys".to_string(), KeySource::Random),
+        (
+            "incremental keys (--incremental)".to_string(),
+            KeySource::Incremental,
+        ),
+        (
+            format!("mnemonic keys (-g {word_count})"),
+            KeySource::Mnemonic {
+                word_count,
+                passphrase: parsed_args.mnemonic_passphrase.clone(),
+                language: parsed_args.language.unwrap_or_default().into(),
+            },
+        ),
+    ];
+    if parsed_args.split_key.is_some() {
+        key_sources.push((
+            "split keys (--split-key)".to_string(),
+            key_source(parsed_args),
+        ));
+    }
+    match parsed_args.derivation_scan() {
+        Some(DerivationScan::Account) => key_sources.push((
+            "NIP-06 accounts (--scan account)".to_string(),
+            key_source(parsed_args),
+        )),
+        Some(DerivationScan::Index) => key_sources.push((
+            "NIP-06 address indices (--scan index)".to_string(),
+            key_source(parsed_args),
+        )),
+        None => {}
+    }
+
+    let mut baseline = 0;
+    for (name, key_source) in key_sources {
+        println!("Benchmarking {name} on a single core for 5 seconds...");
+        let rate = key_source.benchmark(Duration::from_secs(5));
+        if baseline == 0 {
+            baseline = max(1, rate);
+        }
+        println!(
+            "{rate} keys/second ({:.2}x random keys)",
+            rate as f64 / baseline as f64
+        );
+    }
+}
+
+fn benchmark_threads(job: &MiningJob) {
+    println!(
+        "Benchmarking the mining of {} for 5 seconds per thread count...",
+        job.matcher.description()
+    );
+    let results = benchmark_scaling(job, Duration::from_secs(5));
+    let single_thread = max(1, results[0].1);
+    for (threads, rate) in results {
+        println!(
+            "{threads:>4} threads: {rate:>12} hashes/second, {:>10} per thread ({:.0}% scaling efficiency)",
+            rate / threads as u64,
+            rate as f64 / (single_thread * threads as u64) as f64 * 100.0
+        );
     }
 }
@@ -0,0 +1,632 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use nostr::prelude::*;
+
+use crate::encoding::{encode_hex, encode_npub, BitPrefix, BECH32_CHARSET, NPUB_LEN};
+use crate::utils::get_leading_zero_bits;
+
+pub const BECH32_PREFIX: &str = "npub1";
+/// Length of the npub after `npub1`: 52 characters of data and 6 of checksum
+pub const NPUB_BODY_LEN: usize = 58;
+pub const HEX_LEN: usize = 64;
+// Position in the npub body of the last data character, which only holds the
+// last bit of the key followed by 4 bits of padding
+const NPUB_LAST_DATA_CHAR: usize = 51;
+
+/// Outcome of checking a candidate public key against a [`Matcher`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatchResult<'a> {
+    Miss,
+    /// The key partially matches `target`, with a similarity in percent
+    Partial {
+        target: &'a str,
+        similarity: f64,
+    },
+    /// The key matches `target`
+    Hit {
+        target: &'a str,
+        leading_zero_bits: u8,
+    },
+}
+
+/// Decides whether a candidate public key is what we are mining for.
+///
+/// Matchers are shared by all the worker threads, so any state they keep
+/// (like the current difficulty) must be thread safe.
+pub trait Matcher: Send + Sync {
+    /// Check a candidate. `npub` is the bech32 encoding of `pubkey`, or an
+    /// empty string when [`Matcher::needs_npub`] returns false.
+    fn check(&self, pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_>;
+
+    /// Whether `check` looks at the npub. Encoding it is relatively slow, so
+    /// matchers working on the raw key should return false.
+    fn needs_npub(&self) -> bool {
+        true
+    }
+
+    /// Human readable description of the target
+    fn description(&self) -> String;
+
+    /// Probability for a random key to match, to estimate how long mining
+    /// takes. None when it is not known.
+    fn probability(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Number of leading zero bits of the public key (NIP-13 style)
+pub struct LeadingZeroBitsMatcher {
+    // The difficulty that was asked for, the odds of a match are those of
+    // this target however far the scaling went
+    target: u8,
+    best_diff: AtomicU8,
+    scaling: bool,
+}
+
+impl LeadingZeroBitsMatcher {
+    /// With `scaling` the difficulty is raised to every new best match, so
+    /// only keys better than all the previous ones are reported.
+    pub fn new(difficulty: u8, scaling: bool) -> Self {
+        LeadingZeroBitsMatcher {
+            target: difficulty,
+            best_diff: AtomicU8::new(difficulty),
+            scaling,
+        }
+    }
+
+    /// Start from the difficulty a previous run scaled up to
+    pub fn resume_from(self, best_diff: u8) -> Self {
+        self.best_diff.fetch_max(best_diff, Ordering::Relaxed);
+        self
+    }
+
+    pub fn difficulty(&self) -> u8 {
+        self.best_diff.load(Ordering::Relaxed)
+    }
+}
+
+impl Matcher for LeadingZeroBitsMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, _npub: &str) -> MatchResult<'_> {
+        let leading_zero_bits = get_leading_zero_bits(&pubkey.serialize());
+        let best_diff = self.best_diff.load(Ordering::Relaxed);
+        if leading_zero_bits <= best_diff {
+            return MatchResult::Miss;
+        }
+        // update difficulty only if it was set in the first place
+        if self.scaling && best_diff > 0 {
+            self.best_diff
+                .fetch_max(leading_zero_bits, Ordering::Relaxed);
+        }
+        MatchResult::Hit {
+            target: "",
+            leading_zero_bits,
+        }
+    }
+
+    fn needs_npub(&self) -> bool {
+        false
+    }
+
+    fn description(&self) -> String {
+        format!("{} leading zero bits", self.difficulty())
+    }
+
+    // Only keys with more leading zero bits than the difficulty match
+    fn probability(&self) -> Option<f64> {
+        Some(2_f64.powi(-(self.target as i32 + 1)))
+    }
+}
+
+/// Prefix of the public key expressed as hexadecimal
+pub struct HexPrefixMatcher {
+    prefix: String,
+    compiled: Option<BitPrefix>,
+}
+
+impl HexPrefixMatcher {
+    pub fn new(prefix: &str) -> Self {
+        HexPrefixMatcher {
+            prefix: prefix.to_string(),
+            compiled: BitPrefix::from_hex(prefix),
+        }
+    }
+
+    /// Prefix with bit granularity, given as a string of `0`s and `1`s
+    pub fn from_binary(prefix: &str) -> Option<Self> {
+        Some(HexPrefixMatcher {
+            prefix: format!("0b{prefix}"),
+            compiled: Some(BitPrefix::from_binary(prefix)?),
+        })
+    }
+}
+
+impl Matcher for HexPrefixMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, _npub: &str) -> MatchResult<'_> {
+        let is_match = match &self.compiled {
+            Some(compiled) => compiled.matches(&pubkey.serialize()),
+            None => {
+                encode_hex(&pubkey.serialize(), &mut [0; HEX_LEN]).starts_with(self.prefix.as_str())
+            }
+        };
+        if is_match {
+            MatchResult::Hit {
+                target: &self.prefix,
+                leading_zero_bits: 0,
+            }
+        } else {
+            MatchResult::Miss
+        }
+    }
+
+    fn needs_npub(&self) -> bool {
+        false
+    }
+
+    fn description(&self) -> String {
+        format!("vanity hex prefix: '{}'", self.prefix)
+    }
+
+    fn probability(&self) -> Option<f64> {
+        let bits = match self.prefix.strip_prefix("0b") {
+            Some(binary) => binary.len(),
+            None => self.prefix.len() * 4,
+        };
+        Some(2_f64.powi(-(bits as i32)))
+    }
+}
+
+/// Any of the prefixes of the npub, right after `npub1`
+pub struct NpubPrefixMatcher {
+    prefixes: NpubPrefixes,
+}
+
+impl NpubPrefixMatcher {
+    pub fn new(prefixes: Vec<String>) -> Self {
+        NpubPrefixMatcher {
+            prefixes: NpubPrefixes::new(prefixes),
+        }
+    }
+}
+
+impl Matcher for NpubPrefixMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_> {
+        let key = pubkey.serialize();
+        let mut result = MatchResult::Miss;
+        let mut best_similarity = 0.0;
+        for (i, prefix) in self.prefixes.prefixes.iter().enumerate() {
+            let matching = self.prefixes.matching_chars(i, &key, npub);
+            if matching == prefix.len() {
+                return MatchResult::Hit {
+                    target: prefix,
+                    leading_zero_bits: 0,
+                };
+            }
+            let similarity = (matching as f64 / prefix.len() as f64) * 100.0;
+            if similarity > best_similarity {
+                best_similarity = similarity;
+                result = MatchResult::Partial {
+                    target: prefix,
+                    similarity,
+                };
+            }
+        }
+        result
+    }
+
+    fn needs_npub(&self) -> bool {
+        self.prefixes.compiled.is_none()
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "vanity bech32 prefix[es]: 'npub1{:?}'",
+            self.prefixes.prefixes
+        )
+    }
+
+    fn probability(&self) -> Option<f64> {
+        Some(npub_prefixes_probability(&self.prefixes.prefixes))
+    }
+}
+
+// Npub prefixes, compiled to bit prefixes of the raw key when none of them
+// reaches into the checksum
+struct NpubPrefixes {
+    prefixes: Vec<String>,
+    compiled: Option<Vec<BitPrefix>>,
+}
+
+impl NpubPrefixes {
+    fn new(prefixes: Vec<String>) -> Self {
+        let compiled = prefixes
+            .iter()
+            .map(|prefix| BitPrefix::from_npub(prefix))
+            .collect();
+        NpubPrefixes { prefixes, compiled }
+    }
+
+    // Number of leading characters of the i-th prefix matched by the key
+    #[inline]
+    fn matching_chars(&self, i: usize, key: &[u8; 32], npub: &str) -> usize {
+        match &self.compiled {
+            Some(compiled) if compiled[i].matches(key) => self.prefixes[i].len(),
+            Some(compiled) => compiled[i].matching_bits(key) / 5,
+            None => count_matching(
+                self.prefixes[i].chars(),
+                npub.strip_prefix(BECH32_PREFIX).unwrap_or("").chars(),
+            ),
+        }
+    }
+}
+
+/// Any of the suffixes of the npub
+pub struct NpubSuffixMatcher {
+    suffixes: Vec<String>,
+}
+
+impl NpubSuffixMatcher {
+    pub fn new(suffixes: Vec<String>) -> Self {
+        NpubSuffixMatcher { suffixes }
+    }
+}
+
+impl Matcher for NpubSuffixMatcher {
+    fn check(&self, _pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_> {
+        match self
+            .suffixes
+            .iter()
+            .find(|suffix| npub.ends_with(suffix.as_str()))
+        {
+            Some(suffix) => MatchResult::Hit {
+                target: suffix,
+                leading_zero_bits: 0,
+            },
+            None => MatchResult::Miss,
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("vanity bech32 suffix[es]: '...{:?}'", self.suffixes)
+    }
+
+    fn probability(&self) -> Option<f64> {
+        Some(npub_suffixes_probability(&self.suffixes))
+    }
+}
+
+/// Npubs starting with any of the prefixes and ending with any of the suffixes
+pub struct NpubPrefixSuffixMatcher {
+    prefixes: NpubPrefixes,
+    suffixes: Vec<String>,
+    // "prefix...suffix" for every combination, indexed by prefix then suffix
+    targets: Vec<String>,
+}
+
+impl NpubPrefixSuffixMatcher {
+    pub fn new(prefixes: Vec<String>, suffixes: Vec<String>) -> Self {
+        let targets = prefixes
+            .iter()
+            .flat_map(|prefix| {
+                suffixes
+                    .iter()
+                    .map(move |suffix| format!("{prefix}...{suffix}"))
+            })
+            .collect();
+        NpubPrefixSuffixMatcher {
+            prefixes: NpubPrefixes::new(prefixes),
+            suffixes,
+            targets,
+        }
+    }
+}
+
+impl Matcher for NpubPrefixSuffixMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_> {
+        let key = pubkey.serialize();
+        let mut buf = [0u8; NPUB_LEN];
+        let mut encoded: Option<&str> = if npub.is_empty() { None } else { Some(npub) };
+        let mut result = MatchResult::Miss;
+        let mut best_similarity = 0.0;
+        for (i, prefix) in self.prefixes.prefixes.iter().enumerate() {
+            let prefix_matching = self.prefixes.matching_chars(i, &key, npub);
+            for (j, suffix) in self.suffixes.iter().enumerate() {
+                // The suffix needs the checksum, so only encode the npub once
+                // a prefix matches or when it was encoded anyway
+                let suffix_matching = match encoded {
+                    Some(npub) => count_matching(suffix.chars().rev(), npub.chars().rev()),
+                    None if prefix_matching == prefix.len() => {
+                        let npub: &str = encode_npub(&key, &mut buf);
+                        encoded = Some(npub);
+                        count_matching(suffix.chars().rev(), npub.chars().rev())
+                    }
+                    None => 0,
+                };
+                let target = &self.targets[i * self.suffixes.len() + j];
+                if prefix_matching == prefix.len() && suffix_matching == suffix.len() {
+                    return MatchResult::Hit {
+                        target,
+                        leading_zero_bits: 0,
+                    };
+                }
+                let similarity = ((prefix_matching + suffix_matching) as f64
+                    / (prefix.len() + suffix.len()) as f64)
+                    * 100.0;
+                if similarity > best_similarity {
+                    best_similarity = similarity;
+                    result = MatchResult::Partial { target, similarity };
+                }
+            }
+        }
+        result
+    }
+
+    fn needs_npub(&self) -> bool {
+        self.prefixes.compiled.is_none()
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "vanity bech32 prefix[es]: 'npub1{:?}' and suffix[es]: '...{:?}'",
+            self.prefixes.prefixes, self.suffixes
+        )
+    }
+
+    // The prefix is in the data and the suffix mostly in the checksum, which
+    // behaves like independent random characters
+    fn probability(&self) -> Option<f64> {
+        Some(
+            npub_prefixes_probability(&self.prefixes.prefixes)
+                * npub_suffixes_probability(&self.suffixes),
+        )
+    }
+}
+
+/// Any of the infixes anywhere in the npub after `npub1`, or only within its
+/// first `within` characters
+pub struct NpubContainsMatcher {
+    infixes: Vec<String>,
+    within: Option<usize>,
+}
+
+impl NpubContainsMatcher {
+    pub fn new(infixes: Vec<String>, within: Option<usize>) -> Self {
+        NpubContainsMatcher { infixes, within }
+    }
+
+    /// Estimated bits of work, much lower than for a prefix of the same length
+    /// as every position the infix fits in is a chance to match
+    pub fn difficulty(&self) -> f64 {
+        contains_difficulty(&self.infixes, 5.0, self.within.unwrap_or(NPUB_BODY_LEN))
+    }
+}
+
+impl Matcher for NpubContainsMatcher {
+    fn check(&self, _pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_> {
+        let body = npub.strip_prefix(BECH32_PREFIX).unwrap_or("");
+        find_infix(&self.infixes, body, self.within)
+    }
+
+    fn description(&self) -> String {
+        match self.within {
+            Some(within) => format!(
+                "vanity bech32 infix[es]: '{:?}' within the first {within} characters",
+                self.infixes
+            ),
+            None => format!("vanity bech32 infix[es]: '{:?}'", self.infixes),
+        }
+    }
+
+    fn probability(&self) -> Option<f64> {
+        Some(2_f64.powf(-self.difficulty()))
+    }
+}
+
+/// Any of the infixes anywhere in the hex public key, or only within its
+/// first `within` characters
+pub struct HexContainsMatcher {
+    infixes: Vec<String>,
+    within: Option<usize>,
+}
+
+impl HexContainsMatcher {
+    pub fn new(infixes: Vec<String>, within: Option<usize>) -> Self {
+        HexContainsMatcher { infixes, within }
+    }
+
+    /// Estimated bits of work, see [`NpubContainsMatcher::difficulty`]
+    pub fn difficulty(&self) -> f64 {
+        contains_difficulty(&self.infixes, 4.0, self.within.unwrap_or(HEX_LEN))
+    }
+}
+
+impl Matcher for HexContainsMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, _npub: &str) -> MatchResult<'_> {
+        let mut buf = [0; HEX_LEN];
+        find_infix(
+            &self.infixes,
+            encode_hex(&pubkey.serialize(), &mut buf),
+            self.within,
+        )
+    }
+
+    fn needs_npub(&self) -> bool {
+        false
+    }
+
+    fn description(&self) -> String {
+        match self.within {
+            Some(within) => format!(
+                "vanity hex infix[es]: '{:?}' within the first {within} characters",
+                self.infixes
+            ),
+            None => format!("vanity hex infix[es]: '{:?}'", self.infixes),
+        }
+    }
+
+    fn probability(&self) -> Option<f64> {
+        Some(2_f64.powf(-self.difficulty()))
+    }
+}
+
+fn find_infix<'a>(infixes: &'a [String], haystack: &str, within: Option<usize>) -> MatchResult<'a> {
+    let haystack = match within {
+        Some(within) => &haystack[..within.min(haystack.len())],
+        None => haystack,
+    };
+    match infixes
+        .iter()
+        .find(|infix| haystack.contains(infix.as_str()))
+    {
+        Some(infix) => MatchResult::Hit {
+            target: infix,
+            leading_zero_bits: 0,
+        },
+        None => MatchResult::Miss,
+    }
+}
+
+// Each infix of n characters has (len - n + 1) chances to match, each with a
+// probability of 2^-(n * bits_per_char)
+fn contains_difficulty(infixes: &[String], bits_per_char: f64, len: usize) -> f64 {
+    let probability: f64 = infixes
+        .iter()
+        .map(|infix| {
+            let positions = (len + 1).saturating_sub(infix.len()).max(1);
+            positions as f64 * 2_f64.powf(-bits_per_char * infix.len() as f64)
+        })
+        .sum();
+    -probability.min(1.0).log2()
+}
+
+/// Matches when any of its matchers does, to search several targets at once
+#[derive(Default)]
+pub struct AnyMatcher {
+    matchers: Vec<Box<dyn Matcher>>,
+}
+
+impl AnyMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, matcher: impl Matcher + 'static) -> Self {
+        self.push(Box::new(matcher));
+        self
+    }
+
+    pub fn push(&mut self, matcher: Box<dyn Matcher>) {
+        self.matchers.push(matcher);
+    }
+}
+
+impl Matcher for AnyMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_> {
+        let mut result = MatchResult::Miss;
+        for matcher in self.matchers.iter() {
+            match matcher.check(pubkey, npub) {
+                hit @ MatchResult::Hit { .. } => return hit,
+                MatchResult::Partial { target, similarity } => match result {
+                    MatchResult::Partial {
+                        similarity: best, ..
+                    } if best >= similarity => {}
+                    _ => result = MatchResult::Partial { target, similarity },
+                },
+                MatchResult::Miss => {}
+            }
+        }
+        result
+    }
+
+    fn needs_npub(&self) -> bool {
+        self.matchers.iter().any(|matcher| matcher.needs_npub())
+    }
+
+    fn description(&self) -> String {
+        self.matchers
+            .iter()
+            .map(|matcher| matcher.description())
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+
+    // At most the sum of the probabilities of the matchers
+    fn probability(&self) -> Option<f64> {
+        let mut probability = 0.0;
+        for matcher in self.matchers.iter() {
+            probability += matcher.probability()?;
+        }
+        Some(probability.min(1.0))
+    }
+}
+
+/// Probability for a random npub to start with any of the prefixes (after
+/// `npub1`). Prefixes starting with another one are left out, so the others
+/// can't match the same keys and their probabilities add up.
+pub fn npub_prefixes_probability(prefixes: &[String]) -> f64 {
+    alternatives_probability(
+        prefixes,
+        |a, b| a.starts_with(b),
+        |prefix| npub_chars_probability(prefix.chars().enumerate()),
+    )
+}
+
+/// Probability for a random npub to end with any of the suffixes, see
+/// [`npub_prefixes_probability`]
+pub fn npub_suffixes_probability(suffixes: &[String]) -> f64 {
+    alternatives_probability(
+        suffixes,
+        |a, b| a.ends_with(b),
+        |suffix| {
+            npub_chars_probability(
+                suffix
+                    .chars()
+                    .rev()
+                    .enumerate()
+                    .map(|(i, c)| (NPUB_BODY_LEN.wrapping_sub(i + 1), c)),
+            )
+        },
+    )
+}
+
+fn alternatives_probability(
+    targets: &[String],
+    covered_by: impl Fn(&str, &str) -> bool,
+    probability: impl Fn(&str) -> f64,
+) -> f64 {
+    let mut targets: Vec<&str> = targets.iter().map(String::as_str).collect();
+    targets.sort_unstable_by_key(|target| target.len());
+    targets.dedup();
+    let mut kept: Vec<&str> = Vec::new();
+    for target in targets {
+        if !kept.iter().any(|shorter| covered_by(target, shorter)) {
+            kept.push(target);
+        }
+    }
+    kept.into_iter().map(probability).sum::<f64>().min(1.0)
+}
+
+// Probability for the characters to be at their positions in the npub body.
+// Each character holds 5 random bits, except for the last data character
+// which can only be `q` or `s`.
+fn npub_chars_probability(chars: impl Iterator<Item = (usize, char)>) -> f64 {
+    let mut probability = 1.0;
+    for (position, c) in chars {
+        if position >= NPUB_BODY_LEN || !BECH32_CHARSET.contains(c) {
+            return 0.0;
+        }
+        probability *= match position {
+            NPUB_LAST_DATA_CHAR if c == 'q' || c == 's' => 0.5,
+            NPUB_LAST_DATA_CHAR => 0.0,
+            _ => 1.0 / 32.0,
+        };
+    }
+    probability
+}
+
+// Number of leading characters both iterators have in common
+fn count_matching(
+    target: impl Iterator<Item = char>,
+    candidate: impl Iterator<Item = char>,
+) -> usize {
+    target.zip(candidate).take_while(|(a, b)| a == b).count()
+}
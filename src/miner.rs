@@ -0,0 +1,534 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use nostr::bip39::Mnemonic;
+use nostr::bitcoin::util::bip32::DerivationPath;
+use nostr::prelude::*;
+
+use crate::encoding::{encode_npub, NPUB_LEN};
+use crate::keysource::KeyStream;
+pub use crate::keysource::{DerivationScan, KeySource};
+use crate::matcher::{LeadingZeroBitsMatcher, MatchResult, Matcher};
+use crate::signal;
+
+pub const DIFFICULTY_DEFAULT: u8 = 10;
+pub const NEAR_MISS_THRESHOLD: f64 = 75.0;
+
+// Capacity of the channel between the workers and the consumer of events
+const EVENTS_CAPACITY: usize = 1024;
+// How often (in iterations, power of two) a worker checks the iteration limit
+const LIMITS_CHECK_INTERVAL: u64 = 1024;
+
+/// Definition of a mining job, created with [`MiningJob::builder`]
+#[derive(Clone)]
+pub struct MiningJob {
+    pub matcher: Arc<dyn Matcher>,
+    pub threads: usize,
+    pub key_source: KeySource,
+    /// Seed of the candidates, random when not set
+    pub seed: Option<u64>,
+    pub max_matches: Option<u64>,
+    pub max_iterations: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub near_miss_threshold: f64,
+    pub report_misses: bool,
+    /// Statistics of earlier runs of the same job, which the reported
+    /// statistics and the limits, except for the timeout, include
+    pub previous: MiningStats,
+}
+
+impl MiningJob {
+    pub fn builder() -> MiningJobBuilder {
+        MiningJobBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct MiningJobBuilder {
+    matcher: Option<Arc<dyn Matcher>>,
+    threads: Option<usize>,
+    key_source: KeySource,
+    seed: Option<u64>,
+    max_matches: Option<u64>,
+    max_iterations: Option<u64>,
+    timeout: Option<Duration>,
+    near_miss_threshold: Option<f64>,
+    report_misses: bool,
+    previous: MiningStats,
+}
+
+impl MiningJobBuilder {
+    /// What to mine for, defaults to a difficulty of 10 leading zero bits
+    pub fn matcher(mut self, matcher: impl Matcher + 'static) -> Self {
+        self.matcher = Some(Arc::new(matcher));
+        self
+    }
+
+    /// Same as [`MiningJobBuilder::matcher`], for an already shared matcher
+    pub fn shared_matcher(mut self, matcher: Arc<dyn Matcher>) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Number of worker threads, defaults to the number of cores
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn key_source(mut self, key_source: KeySource) -> Self {
+        self.key_source = key_source;
+        self
+    }
+
+    /// Generate the candidates deterministically from this seed, with a
+    /// different stream for every thread, to make the search reproducible
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Stop after this many keys matching the target were found
+    pub fn max_matches(mut self, max_matches: u64) -> Self {
+        self.max_matches = Some(max_matches);
+        self
+    }
+
+    /// Stop after this many candidates were tried
+    pub fn max_iterations(mut self, max_iterations: u64) -> Self {
+        self.max_iterations = Some(max_iterations);
+        self
+    }
+
+    /// Stop after this much time has passed in this run, not counting the
+    /// time of the runs it resumes
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Minimum similarity (in percent) for a partial match to be reported
+    pub fn near_miss_threshold(mut self, threshold: f64) -> Self {
+        self.near_miss_threshold = Some(threshold);
+        self
+    }
+
+    /// Report every non-matching public key as a [`MiningEvent::Miss`]
+    pub fn report_misses(mut self, report_misses: bool) -> Self {
+        self.report_misses = report_misses;
+        self
+    }
+
+    /// Continue a job that already ran, e.g. from a checkpoint
+    pub fn resume(mut self, previous: MiningStats) -> Self {
+        self.previous = previous;
+        self
+    }
+
+    pub fn build(self) -> MiningJob {
+        MiningJob {
+            matcher: self
+                .matcher
+                .unwrap_or_else(|| Arc::new(LeadingZeroBitsMatcher::new(DIFFICULTY_DEFAULT, true))),
+            threads: self.threads.unwrap_or_else(num_cpus::get).max(1),
+            key_source: self.key_source,
+            seed: self.seed,
+            max_matches: self.max_matches,
+            max_iterations: self.max_iterations,
+            timeout: self.timeout,
+            near_miss_threshold: self.near_miss_threshold.unwrap_or(NEAR_MISS_THRESHOLD),
+            report_misses: self.report_misses,
+            previous: self.previous,
+        }
+    }
+}
+
+/// A key pair reported by the miner
+#[derive(Clone, Debug)]
+pub struct FoundKey {
+    pub keys: Keys,
+    pub mnemonic: Option<Mnemonic>,
+    /// With split-key mining, the tweak to add to the secret key of the
+    /// customer, `keys` then only has the public key
+    pub tweak: Option<SecretKey>,
+    /// NIP-06 derivation path of the key when scanning the derivations of a
+    /// mnemonic
+    pub derivation_path: Option<DerivationPath>,
+    /// The vanity target that was matched, empty for difficulty mining
+    pub target: String,
+    pub leading_zero_bits: u8,
+    /// Similarity in percent with the target
+    pub similarity: f64,
+    /// Total iterations of all threads when the key was found
+    pub iterations: u64,
+    pub elapsed: Duration,
+}
+
+pub enum MiningEvent {
+    /// A key matching the target
+    Found(FoundKey),
+    /// The best partial match so far, above the near miss threshold
+    NearMiss(FoundKey),
+    /// A non-matching public key, only sent when misses are reported
+    Miss(XOnlyPublicKey),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MiningStats {
+    pub iterations: u64,
+    pub matches: u64,
+    pub elapsed: Duration,
+    pub best_match: Option<FoundKey>,
+}
+
+// Iterations of a single worker, on its own cache line so that the workers
+// don't slow each other down
+#[repr(align(128))]
+#[derive(Default)]
+struct Counter(AtomicU64);
+
+struct Shared {
+    job: MiningJob,
+    started: Instant,
+    cancelled: AtomicBool,
+    counters: Vec<Counter>,
+    matches: AtomicU64,
+    // Similarity of the best match as f64 bits, to skip the lock for the
+    // partial matches that are not better
+    best_similarity: AtomicU64,
+    best_match: Mutex<Option<FoundKey>>,
+}
+
+impl Shared {
+    fn iterations(&self) -> u64 {
+        self.job.previous.iterations
+            + self
+                .counters
+                .iter()
+                .map(|counter| counter.0.load(Ordering::Relaxed))
+                .sum::<u64>()
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.job.previous.elapsed + self.started.elapsed()
+    }
+
+    // Cancel the job once the timeout of this run has passed, by the clock
+    // rather than by the iterations, as slow key sources take seconds for a
+    // thousand keys. Parked rather than sleeping, so that stopping the miner
+    // wakes it.
+    fn watch_timeout(&self, timeout: Duration) {
+        while !self.cancelled.load(Ordering::Relaxed) {
+            match timeout.checked_sub(self.started.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => thread::park_timeout(remaining),
+                _ => self.cancelled.store(true, Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn best_similarity(&self) -> f64 {
+        f64::from_bits(self.best_similarity.load(Ordering::Relaxed))
+    }
+
+    fn stats(&self) -> MiningStats {
+        MiningStats {
+            iterations: self.iterations(),
+            matches: self
+                .matches
+                .load(Ordering::Relaxed)
+                .min(self.job.max_matches.unwrap_or(u64::MAX)),
+            elapsed: self.elapsed(),
+            best_match: self.best_match.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// A running mining job
+pub struct Miner {
+    shared: Arc<Shared>,
+    events: Receiver<MiningEvent>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Miner {
+    /// Spawn the worker threads of the job
+    pub fn start(job: MiningJob) -> Miner {
+        let (sender, events) = sync_channel(EVENTS_CAPACITY);
+        let previous = &job.previous;
+        let best_similarity = previous
+            .best_match
+            .as_ref()
+            .map_or(0.0, |best| best.similarity);
+        let shared = Arc::new(Shared {
+            started: Instant::now(),
+            cancelled: AtomicBool::new(previous.matches >= job.max_matches.unwrap_or(u64::MAX)),
+            counters: (0..job.threads).map(|_| Counter::default()).collect(),
+            matches: AtomicU64::new(previous.matches),
+            best_similarity: AtomicU64::new(best_similarity.to_bits()),
+            best_match: Mutex::new(previous.best_match.clone()),
+            job,
+        });
+
+        let mut handles: Vec<JoinHandle<()>> = (0..shared.job.threads)
+            .map(|index| {
+                let shared = shared.clone();
+                let sender = sender.clone();
+                thread::spawn(move || Worker::new(index, shared, sender).run())
+            })
+            .collect();
+        if let Some(timeout) = shared.job.timeout {
+            let shared = shared.clone();
+            handles.push(thread::spawn(move || shared.watch_timeout(timeout)));
+        }
+
+        Miner {
+            shared,
+            events,
+            handles,
+        }
+    }
+
+    /// Run the job to completion, handing every event to `callback`
+    pub fn run<F>(job: MiningJob, mut callback: F) -> MiningStats
+    where
+        F: FnMut(MiningEvent),
+    {
+        let miner = Miner::start(job);
+        for event in miner.events() {
+            callback(event);
+        }
+        miner.join()
+    }
+
+    /// Events sent by the workers. Iterating ends once all workers stopped.
+    pub fn events(&self) -> &Receiver<MiningEvent> {
+        &self.events
+    }
+
+    /// Ask all workers to stop
+    pub fn stop(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop the workers once SIGINT or SIGTERM was received. Call it on
+    /// every event, as they can keep coming without a pause. True the first
+    /// time it stops them.
+    pub fn stop_if_interrupted(&self) -> bool {
+        if !signal::interrupted() || self.is_stopped() {
+            return false;
+        }
+        self.stop();
+        true
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> MiningStats {
+        self.shared.stats()
+    }
+
+    /// Iterations of every thread since the miner started
+    pub fn thread_iterations(&self) -> Vec<u64> {
+        self.shared
+            .counters
+            .iter()
+            .map(|counter| counter.0.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Stop the workers and wait for them to finish
+    pub fn join(self) -> MiningStats {
+        self.stop();
+        // Unblock workers waiting on a full channel
+        drop(self.events);
+        for handle in self.handles {
+            // Wake up the timeout watcher
+            handle.thread().unpark();
+            handle.join().expect("Mining thread panicked");
+        }
+        self.shared.stats()
+    }
+}
+
+struct Worker {
+    index: usize,
+    shared: Arc<Shared>,
+    sender: SyncSender<MiningEvent>,
+    stream: Box<dyn KeyStream>,
+    iterations: u64,
+    // Best similarity seen by this worker, only merged into the shared best
+    // match when it beats it
+    best_similarity: Cell<f64>,
+}
+
+impl Worker {
+    fn new(index: usize, shared: Arc<Shared>, sender: SyncSender<MiningEvent>) -> Self {
+        let stream = shared
+            .job
+            .key_source
+            .stream(shared.job.seed, index, shared.job.threads);
+        Worker {
+            index,
+            shared,
+            sender,
+            stream,
+            iterations: 0,
+            best_similarity: Cell::new(0.0),
+        }
+    }
+
+    fn run(mut self) {
+        while !self.shared.cancelled.load(Ordering::Relaxed) {
+            // The stream ran out of candidates
+            let Some(public_key) = self.stream.next_public_key() else {
+                break;
+            };
+            self.iterations += 1;
+            self.shared.counters[self.index]
+                .0
+                .store(self.iterations, Ordering::Relaxed);
+            if self.iterations & (LIMITS_CHECK_INTERVAL - 1) == 0 {
+                self.check_limits();
+            }
+            if !self.check(public_key) {
+                break;
+            }
+        }
+    }
+
+    fn check_limits(&self) {
+        let job = &self.shared.job;
+        if job
+            .max_iterations
+            .is_some_and(|max| self.shared.iterations() >= max)
+        {
+            self.shared.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Check the candidate against the matcher and report it. Returns false
+    // when nobody is listening for events anymore.
+    fn check(&self, public_key: XOnlyPublicKey) -> bool {
+        let matcher = &self.shared.job.matcher;
+        let mut buf = [0u8; NPUB_LEN];
+        let npub = if matcher.needs_npub() {
+            encode_npub(&public_key.serialize(), &mut buf)
+        } else {
+            ""
+        };
+
+        match matcher.check(&public_key, npub) {
+            MatchResult::Hit {
+                target,
+                leading_zero_bits,
+            } => {
+                let found = self.found_key(target.to_string(), leading_zero_bits, 100.0);
+                self.report_match(found)
+            }
+            MatchResult::Partial { target, similarity } => {
+                self.update_best_match(target, similarity)
+            }
+            MatchResult::Miss if self.shared.job.report_misses => !matches!(
+                self.sender.try_send(MiningEvent::Miss(public_key)),
+                Err(TrySendError::Disconnected(_))
+            ),
+            MatchResult::Miss => true,
+        }
+    }
+
+    fn update_best_match(&self, target: &str, similarity: f64) -> bool {
+        if similarity <= self.best_similarity.get() {
+            return true;
+        }
+        self.best_similarity.set(similarity);
+        if similarity <= self.shared.best_similarity() {
+            return true;
+        }
+
+        let mut best_match = self.shared.best_match.lock().unwrap();
+        if best_match
+            .as_ref()
+            .is_some_and(|best| similarity <= best.similarity)
+        {
+            return true;
+        }
+        let found = self.found_key(target.to_string(), 0, similarity);
+        *best_match = Some(found.clone());
+        self.shared
+            .best_similarity
+            .store(similarity.to_bits(), Ordering::Relaxed);
+        drop(best_match);
+
+        if similarity >= self.shared.job.near_miss_threshold {
+            return self.sender.send(MiningEvent::NearMiss(found)).is_ok();
+        }
+        true
+    }
+
+    fn report_match(&self, found: FoundKey) -> bool {
+        let max_matches = self.shared.job.max_matches.unwrap_or(u64::MAX);
+        let previous = self.shared.matches.fetch_add(1, Ordering::Relaxed);
+        if previous >= max_matches {
+            return true;
+        }
+        if previous + 1 == max_matches {
+            self.shared.cancelled.store(true, Ordering::Relaxed);
+        }
+        self.sender.send(MiningEvent::Found(found)).is_ok()
+    }
+
+    fn found_key(&self, target: String, leading_zero_bits: u8, similarity: f64) -> FoundKey {
+        let (keys, mnemonic) = self.stream.keys();
+        FoundKey {
+            keys,
+            mnemonic,
+            tweak: self.stream.tweak(),
+            derivation_path: self.stream.derivation_path(),
+            target,
+            leading_zero_bits,
+            similarity,
+            iterations: self.shared.iterations(),
+            elapsed: self.shared.elapsed(),
+        }
+    }
+}
+
+/// Hashes per second of the job with 1, 2, 4... up to `job.threads` threads,
+/// mining for `duration` with each thread count
+pub fn benchmark_scaling(job: &MiningJob, duration: Duration) -> Vec<(usize, u64)> {
+    let mut thread_counts = Vec::new();
+    let mut threads = 1;
+    while threads < job.threads {
+        thread_counts.push(threads);
+        threads *= 2;
+    }
+    thread_counts.push(job.threads);
+
+    thread_counts
+        .into_iter()
+        .map(|threads| {
+            let mut job = job.clone();
+            job.threads = threads;
+            (threads, benchmark(job, duration) as u64)
+        })
+        .collect()
+}
+
+/// Hashes per second of the job, mining with all its threads for `duration`.
+/// The matcher is used as is, so it should not keep state (like a scaling
+/// difficulty) that mining after the benchmark depends on.
+pub fn benchmark(mut job: MiningJob, duration: Duration) -> f64 {
+    job.timeout = Some(duration);
+    job.max_matches = None;
+    job.max_iterations = None;
+    job.report_misses = false;
+    job.previous = MiningStats::default();
+    let stats = Miner::run(job, |_| {});
+    stats.iterations as f64 / stats.elapsed.as_secs_f64()
+}
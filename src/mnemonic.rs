@@ -1,49 +1,259 @@
-use std::process::exit;
-
+use clap::ValueEnum;
+use colored::Colorize;
+use nostr::bip39::{self, Language, Mnemonic};
+use nostr::bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
+use nostr::bitcoin::Network;
 use nostr::prelude::*;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::cli::{AccountArgs, MnemonicCommand, MnemonicGenerateArgs, MnemonicRestoreArgs};
+use crate::error::{RanaError, UnknownWord};
+use crate::prompt;
+
+/// Number of words a BIP-39 mnemonic can have
+pub const WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
 
-use crate::cli::CLIArgs;
+/// Highest account and address index, above which BIP-32 indices are hardened
+pub const MAX_ACCOUNT: u32 = (1 << 31) - 1;
 
-pub fn handle_mnemonic(parsed_args: &CLIArgs) {
-    if parsed_args.word_count > 0 {
-        let mut word_count = parsed_args.word_count;
-        if parsed_args.word_count == 0 || parsed_args.word_count > 24 {
-            word_count = 12;
+/// The standard BIP-39 word lists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MnemonicLanguage {
+    #[default]
+    English,
+    ChineseSimplified,
+    ChineseTraditional,
+    Czech,
+    French,
+    Italian,
+    Japanese,
+    Korean,
+    Spanish,
+}
+
+impl From<MnemonicLanguage> for Language {
+    fn from(language: MnemonicLanguage) -> Self {
+        match language {
+            MnemonicLanguage::English => Language::English,
+            MnemonicLanguage::ChineseSimplified => Language::SimplifiedChinese,
+            MnemonicLanguage::ChineseTraditional => Language::TraditionalChinese,
+            MnemonicLanguage::Czech => Language::Czech,
+            MnemonicLanguage::French => Language::French,
+            MnemonicLanguage::Italian => Language::Italian,
+            MnemonicLanguage::Japanese => Language::Japanese,
+            MnemonicLanguage::Korean => Language::Korean,
+            MnemonicLanguage::Spanish => Language::Spanish,
         }
-        let mnemonic = Keys::generate_mnemonic(word_count).expect("Couldn't not generate mnemonic");
-        let keys = Keys::from_mnemonic(mnemonic.to_string(), None).expect("");
-
-        println!(
-            "Mnemonic: {}\nPublic key: {}\nPrivate key: {}",
-            mnemonic,
-            keys.public_key()
-                .to_bech32()
-                .expect("Could not get public key bech32 conversion"),
-            keys.secret_key()
-                .expect("Could not get secret key")
-                .to_bech32()
-                .expect("Could not get secret key bech32 conversion")
-        );
-        exit(0)
-    }
-
-    if !parsed_args.mnemonic.is_empty() {
-        let keys = Keys::from_mnemonic(
-            parsed_args.mnemonic.to_string(),
-            Some(parsed_args.mnemonic_passphrase.to_string()),
-        )
-        .expect("Error creating key pair from mnemonic");
-
-        println!(
-            "Public key: {}\nPrivate key: {}",
-            keys.public_key()
-                .to_bech32()
-                .expect("Could not get public key bech32 conversion"),
-            keys.secret_key()
-                .expect("Could not get secret key")
-                .to_bech32()
-                .expect("Could not get secret key bech32 conversion")
+    }
+}
+
+pub fn handle_mnemonic(command: &MnemonicCommand) -> Result<()> {
+    match command {
+        MnemonicCommand::Generate(args) => generate(args),
+        MnemonicCommand::Restore(args) => restore(args),
+    }
+}
+
+/// `rana mnemonic generate`: show a new mnemonic and its keys
+pub fn generate(args: &MnemonicGenerateArgs) -> Result<()> {
+    if !WORD_COUNTS.contains(&args.words) {
+        return Err(RanaError::InvalidValue {
+            option: "-w, --words",
+            reason: "mnemonics have 12, 15, 18, 21 or 24 words".to_string(),
+        }
+        .into());
+    }
+    let mnemonic = generate_mnemonic(args.words, args.language.into());
+    println!("Mnemonic: {mnemonic}");
+    Ok(print_accounts(&mnemonic, &args.passphrase, &args.accounts)?)
+}
+
+/// `rana mnemonic restore`: show the keys of a mnemonic
+pub fn restore(args: &MnemonicRestoreArgs) -> Result<()> {
+    let phrase = match args.mnemonic.as_deref() {
+        Some(phrase) if phrase != "-" => phrase.to_string(),
+        _ => prompt::read_secret("Mnemonic: ")?,
+    };
+    let mnemonic = parse_mnemonic(&phrase, args.language.map(Language::from))?;
+    // Japanese words are separated by ideographic spaces
+    let separator = |c: char| c == ' ' || c == '\u{3000}';
+    if phrase.to_lowercase() != phrase
+        || phrase.split(separator).any(str::is_empty)
+        || phrase.contains(|c: char| c.is_whitespace() && !separator(c))
+    {
+        eprintln!(
+            "{}",
+            "The case and spacing of the mnemonic were corrected.".yellow()
         );
-        exit(0);
     }
+    Ok(print_accounts(&mnemonic, &args.passphrase, &args.accounts)?)
+}
+
+/// A new mnemonic from the randomness of the operating system
+pub fn generate_mnemonic(word_count: usize, language: Language) -> Mnemonic {
+    // 4 bytes of entropy for every 3 words
+    let mut entropy = [0u8; 32];
+    let entropy = &mut entropy[..word_count * 4 / 3];
+    OsRng.fill_bytes(entropy);
+    Mnemonic::from_entropy_in(language, entropy).expect("Valid number of words")
+}
+
+/// Parse a mnemonic, telling what is wrong with it: unknown words, with the
+/// closest words of the list, the number of words or the checksum. The words
+/// can be in any case and separated by any whitespace. Without a language,
+/// the list with the most of the words is used.
+pub fn parse_mnemonic(mnemonic: &str, language: Option<Language>) -> Result<Mnemonic, RanaError> {
+    let normalized = normalize_mnemonic(mnemonic);
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+    let languages = match language {
+        Some(language) => vec![language],
+        None => languages_of(&words),
+    };
+    let list = languages[0].word_list();
+    let unknown_words: Vec<UnknownWord> = words
+        .iter()
+        .enumerate()
+        .filter(|(_, word)| !list.contains(word))
+        .map(|(index, word)| UnknownWord {
+            position: index + 1,
+            word: word.to_string(),
+            suggestions: closest_words(word, list),
+        })
+        .collect();
+    if !unknown_words.is_empty() {
+        return Err(RanaError::UnknownWords(unknown_words));
+    }
+    if !WORD_COUNTS.contains(&words.len()) {
+        return Err(RanaError::MnemonicWordCount(words.len()));
+    }
+    // The English and French lists share words, a mnemonic can be made of
+    // both. The checksum tells which one it is.
+    for language in languages
+        .into_iter()
+        .filter(|language| words.iter().all(|word| language.find_word(word).is_some()))
+    {
+        match Mnemonic::parse_in_normalized(language, &normalized) {
+            Ok(mnemonic) => return Ok(mnemonic),
+            Err(bip39::Error::InvalidChecksum) => continue,
+            Err(e) => return Err(RanaError::InvalidMnemonic(e.to_string())),
+        }
+    }
+    Err(RanaError::MnemonicChecksum)
+}
+
+// The languages, those whose lists have the most of the words first
+fn languages_of(words: &[&str]) -> Vec<Language> {
+    let mut languages = Language::all().to_vec();
+    languages.sort_by_cached_key(|language| {
+        let known = words
+            .iter()
+            .filter(|word| language.find_word(word).is_some())
+            .count();
+        std::cmp::Reverse(known)
+    });
+    languages
+}
+
+/// The words of a mnemonic in lowercase and NFKD, like the word lists,
+/// separated by single spaces
+pub fn normalize_mnemonic(mnemonic: &str) -> String {
+    mnemonic
+        .nfkd()
+        .collect::<String>()
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Words of the list that a mistyped word was probably meant to be: the ones
+// at most 2 edits away, closest first. Starting with the same 4 letters, which
+// are unique in the lists in latin script, counts as a single edit.
+fn closest_words(word: &str, list: &[&str]) -> Vec<String> {
+    let prefix: String = word.chars().take(4).collect();
+    let mut candidates: Vec<(usize, &str)> = list
+        .iter()
+        .filter_map(|candidate| {
+            let mut distance = strsim::damerau_levenshtein(word, candidate);
+            if prefix.chars().count() == 4 && candidate.starts_with(&prefix) {
+                distance = distance.min(1);
+            }
+            (distance <= 2).then_some((distance, *candidate))
+        })
+        .collect();
+    candidates.sort_by_key(|(distance, _)| *distance);
+    candidates
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Root key of the NIP-06 derivations of a mnemonic
+pub fn master_key(mnemonic: &Mnemonic, passphrase: &str) -> ExtendedPrivKey {
+    let seed = mnemonic.to_seed(passphrase);
+    ExtendedPrivKey::new_master(Network::Bitcoin, &seed)
+        .expect("A 64-byte seed is a valid master key")
+}
+
+/// NIP-06 derivation path of an account and address index:
+/// `m/44'/1237'/<account>'/0/<index>`
+pub fn derivation_path(account: u32, index: u32) -> Result<DerivationPath, RanaError> {
+    let invalid = |option, value| RanaError::InvalidValue {
+        option,
+        reason: format!("{value} is above the maximum of {MAX_ACCOUNT}"),
+    };
+    Ok(DerivationPath::from(vec![
+        ChildNumber::Hardened { index: 44 },
+        ChildNumber::Hardened { index: 1237 },
+        ChildNumber::from_hardened_idx(account).map_err(|_| invalid("account", account))?,
+        ChildNumber::Normal { index: 0 },
+        ChildNumber::from_normal_idx(index).map_err(|_| invalid("index", index))?,
+    ]))
+}
+
+/// Keys of an account and address index of the mnemonic of `master`. Account
+/// 0 and index 0 are the keys of `Keys::from_mnemonic`.
+pub fn derive_keys(master: &ExtendedPrivKey, account: u32, index: u32) -> Result<Keys, RanaError> {
+    let path = derivation_path(account, index)?;
+    let child = master
+        .derive_priv(SECP256K1, &path)
+        .map_err(|e| RanaError::InvalidValue {
+            option: "derivation path",
+            reason: e.to_string(),
+        })?;
+    Ok(Keys::new(child.private_key))
+}
+
+fn print_accounts(
+    mnemonic: &Mnemonic,
+    passphrase: &str,
+    accounts: &AccountArgs,
+) -> Result<(), RanaError> {
+    let master = master_key(mnemonic, passphrase);
+    for account in accounts.range() {
+        let keys = derive_keys(&master, account, 0)?;
+        if accounts.is_set() {
+            println!("Account {account} ({}):", derivation_path(account, 0)?);
+        }
+        print_keys(&keys);
+    }
+    Ok(())
+}
+
+fn print_keys(keys: &Keys) {
+    println!(
+        "Public key: {}\nPrivate key: {}",
+        keys.public_key()
+            .to_bech32()
+            .expect("Could not get public key bech32 conversion"),
+        keys.secret_key()
+            .expect("Could not get secret key")
+            .to_bech32()
+            .expect("Could not get secret key bech32 conversion")
+    );
 }
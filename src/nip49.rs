@@ -0,0 +1,149 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use clap::ValueEnum;
+use nostr::bech32::{self, FromBase32, ToBase32, Variant};
+use nostr::prelude::*;
+use rand::RngCore;
+use unicode_normalization::UnicodeNormalization;
+
+pub const NCRYPTSEC_HRP: &str = "ncryptsec";
+/// Default scrypt cost, 2^16 rounds take about 0.1 second and 64 MiB
+pub const LOG_N_DEFAULT: u8 = 16;
+
+const VERSION: u8 = 0x02;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+// version, log_n, salt, nonce, key security byte, encrypted key and tag
+const PAYLOAD_LEN: usize = 1 + 1 + SALT_LEN + NONCE_LEN + 1 + 32 + 16;
+
+/// How the secret key was handled before being encrypted, stored in the
+/// `ncryptsec` so that clients can warn about keys that may have leaked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeySecurity {
+    /// The key is known to have been handled insecurely
+    Insecure,
+    /// The key is not known to have been handled insecurely
+    Secure,
+    /// It is not tracked how the key was handled
+    #[default]
+    Unknown,
+}
+
+impl KeySecurity {
+    fn byte(&self) -> u8 {
+        match self {
+            KeySecurity::Insecure => 0x00,
+            KeySecurity::Secure => 0x01,
+            KeySecurity::Unknown => 0x02,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(KeySecurity::Insecure),
+            0x01 => Some(KeySecurity::Secure),
+            0x02 => Some(KeySecurity::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// Password and settings to encrypt the found secret keys with
+#[derive(Clone)]
+pub struct Encryption {
+    pub password: String,
+    pub log_n: u8,
+    pub key_security: KeySecurity,
+}
+
+impl Encryption {
+    pub fn encrypt(&self, secret_key: &SecretKey) -> Result<String> {
+        encrypt(secret_key, &self.password, self.log_n, self.key_security)
+    }
+}
+
+/// Encrypt the secret key with a password as described in NIP-49, returning
+/// an `ncryptsec1…` string
+pub fn encrypt(
+    secret_key: &SecretKey,
+    password: &str,
+    log_n: u8,
+    key_security: KeySecurity,
+) -> Result<String> {
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let key = derive_key(password, &salt, log_n)?;
+    let associated_data = [key_security.byte()];
+    let ciphertext = XChaCha20Poly1305::new(&key.into())
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &secret_key.secret_bytes(),
+                aad: &associated_data,
+            },
+        )
+        .map_err(|_| "Could not encrypt the secret key")?;
+
+    let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+    payload.push(VERSION);
+    payload.push(log_n);
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&associated_data);
+    payload.extend_from_slice(&ciphertext);
+
+    Ok(bech32::encode(
+        NCRYPTSEC_HRP,
+        payload.to_base32(),
+        Variant::Bech32,
+    )?)
+}
+
+/// Decrypt an `ncryptsec1…` string with its password
+pub fn decrypt(ncryptsec: &str, password: &str) -> Result<(SecretKey, KeySecurity)> {
+    let (hrp, data, _) = bech32::decode(ncryptsec)?;
+    if hrp != NCRYPTSEC_HRP {
+        return Err(format!("Expected an {NCRYPTSEC_HRP} string, found {hrp}").into());
+    }
+    let payload = Vec::<u8>::from_base32(&data)?;
+    if payload.len() != PAYLOAD_LEN || payload[0] != VERSION {
+        return Err("Unsupported ncryptsec version".into());
+    }
+
+    let log_n = payload[1];
+    let salt = &payload[2..2 + SALT_LEN];
+    let nonce = &payload[2 + SALT_LEN..2 + SALT_LEN + NONCE_LEN];
+    let associated_data = &payload[2 + SALT_LEN + NONCE_LEN..3 + SALT_LEN + NONCE_LEN];
+    let ciphertext = &payload[3 + SALT_LEN + NONCE_LEN..];
+    let key_security =
+        KeySecurity::from_byte(associated_data[0]).ok_or("Invalid key security byte")?;
+
+    let key = derive_key(password, salt, log_n)?;
+    let secret = XChaCha20Poly1305::new(&key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| "Wrong password or corrupted ncryptsec")?;
+
+    Ok((SecretKey::from_slice(&secret)?, key_security))
+}
+
+// scrypt of the NFKC normalized password, so that it can be typed the same
+// way everywhere
+fn derive_key(password: &str, salt: &[u8], log_n: u8) -> Result<[u8; 32]> {
+    let password: String = password.nfkc().collect();
+    let params = scrypt::Params::new(log_n, 8, 1, 32)
+        .map_err(|_| format!("Invalid scrypt cost: log_n = {log_n}"))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| "Could not derive the encryption key")?;
+    Ok(key)
+}
@@ -0,0 +1,573 @@
+use std::cmp::max;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use colored::Colorize;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::estimate::format_duration;
+use crate::matcher::BECH32_PREFIX;
+use crate::miner::{FoundKey, MiningStats};
+use crate::nip49::Encryption;
+use crate::utils::{print_divider, print_keys, print_qr, print_qr_code};
+
+/// How the found keys are written to stdout. Everything else (progress,
+/// estimates, summaries for humans) goes to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// A single JSON array with all the records, written when mining ends
+    Json,
+    /// One JSON record per line, written as soon as it is available
+    Ndjson,
+}
+
+/// A found key pair, as written in the JSON formats
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyRecord {
+    pub hex_public_key: String,
+    pub npub: String,
+    /// Secret parts are left out when they were saved to a key file
+    pub hex_secret_key: Option<String>,
+    pub nsec: Option<String>,
+    /// The secret key encrypted with a password (NIP-49), if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ncryptsec: Option<String>,
+    /// With split-key mining, the tweak to add to the secret key of the
+    /// customer instead of the secret key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tweak: Option<String>,
+    pub mnemonic: Option<String>,
+    /// NIP-06 derivation path of the key, when scanning a mnemonic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
+    /// Key file the secret parts were saved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saved_to: Option<String>,
+    /// The vanity target that was matched, empty for difficulty mining
+    pub target: String,
+    pub leading_zero_bits: u8,
+    /// Similarity in percent with the target
+    pub similarity: f64,
+    pub iterations: u64,
+    pub elapsed_seconds: f64,
+}
+
+impl From<&FoundKey> for KeyRecord {
+    fn from(found: &FoundKey) -> Self {
+        // Split-key mining only finds a tweak, not the secret key
+        let secret_key = found.keys.secret_key().ok();
+        KeyRecord {
+            hex_public_key: found.keys.public_key().to_string(),
+            npub: found
+                .keys
+                .public_key()
+                .to_bech32()
+                .expect("Could not get public key bech32 conversion"),
+            hex_secret_key: secret_key.map(|key| key.display_secret().to_string()),
+            nsec: secret_key.map(|key| {
+                key.to_bech32()
+                    .expect("Could not get secret key bech32 conversion")
+            }),
+            ncryptsec: None,
+            tweak: found.tweak.map(|tweak| tweak.display_secret().to_string()),
+            mnemonic: found.mnemonic.as_ref().map(ToString::to_string),
+            derivation_path: found.derivation_path.as_ref().map(ToString::to_string),
+            saved_to: None,
+            target: found.target.clone(),
+            leading_zero_bits: found.leading_zero_bits,
+            similarity: found.similarity,
+            iterations: found.iterations,
+            elapsed_seconds: found.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+impl KeyRecord {
+    /// The record without its secret parts
+    pub fn public(mut self) -> Self {
+        self.hex_secret_key = None;
+        self.nsec = None;
+        self.ncryptsec = None;
+        self.tweak = None;
+        self.mnemonic = None;
+        self
+    }
+
+    /// The record without its secret parts, pointing to where they were saved
+    fn redacted(self, key_file: &KeyFile) -> Self {
+        let mut record = self.public();
+        record.saved_to = Some(key_file.path().display().to_string());
+        record
+    }
+}
+
+/// Totals of a mining run, as written in the JSON formats
+#[derive(Clone, Debug, Serialize)]
+pub struct SummaryRecord {
+    pub iterations: u64,
+    pub matches: u64,
+    pub elapsed_seconds: f64,
+    /// Hashes per second
+    pub rate: f64,
+    pub best_near_miss: Option<KeyRecord>,
+}
+
+impl From<&MiningStats> for SummaryRecord {
+    fn from(stats: &MiningStats) -> Self {
+        let elapsed_seconds = stats.elapsed.as_secs_f64();
+        SummaryRecord {
+            iterations: stats.iterations,
+            matches: stats.matches,
+            elapsed_seconds,
+            rate: stats.iterations as f64 / elapsed_seconds.max(0.001),
+            best_near_miss: stats.best_match.as_ref().map(KeyRecord::from),
+        }
+    }
+}
+
+/// Periodic report of a running search (`--progress`), as written in the
+/// NDJSON format
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgressRecord {
+    pub iterations: u64,
+    pub elapsed_seconds: f64,
+    /// Hashes per second since the last report
+    pub rate: f64,
+    /// Hashes per second since the start
+    pub average_rate: f64,
+    pub thread_rates: Vec<f64>,
+    /// Most leading zero bits found, when mining for a difficulty
+    pub best_leading_zero_bits: Option<u8>,
+    /// Only the public key, its secret key was reported as a near miss
+    pub best_near_miss: Option<KeyRecord>,
+    /// Chance in percent of having found a match by now
+    pub chance: Option<f64>,
+}
+
+/// A line of NDJSON output, or an element of the JSON array
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Match(KeyRecord),
+    NearMiss(KeyRecord),
+    Progress(ProgressRecord),
+    Summary(SummaryRecord),
+}
+
+/// Writes the results of a mining run in the chosen format
+pub struct Output {
+    format: OutputFormat,
+    qr: bool,
+    key_file: Option<KeyFile>,
+    encryption: Option<Encryption>,
+    // Public key of the last near miss saved to the key file
+    saved_near_miss: Option<String>,
+    // Records held back until the end of the run for the JSON array
+    records: Vec<Record>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, qr: bool) -> Self {
+        Output {
+            format,
+            qr,
+            key_file: None,
+            encryption: None,
+            saved_near_miss: None,
+            records: Vec::new(),
+        }
+    }
+
+    /// Save the secret keys to `key_file` and only show the public keys
+    pub fn with_key_file(mut self, key_file: KeyFile) -> Self {
+        self.key_file = Some(key_file);
+        self
+    }
+
+    /// Also show the secret keys encrypted with a password (NIP-49), and
+    /// use that instead of the nsec for QR codes
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn found(&mut self, found: &FoundKey) {
+        let record = self.key_record(found);
+        self.save(&record, false);
+        match (self.format, &self.key_file) {
+            (OutputFormat::Text, Some(key_file)) => print_saved_key(found, key_file),
+            (OutputFormat::Text, None) => print_found_key(found, &record, self.qr),
+            _ => {
+                let record = self.public_record(record);
+                self.record(Record::Match(record))
+            }
+        }
+    }
+
+    pub fn near_miss(&mut self, found: &FoundKey) {
+        let record = self.key_record(found);
+        self.save(&record, true);
+        match (self.format, &self.key_file) {
+            (OutputFormat::Text, Some(key_file)) => print_saved_near_miss(found, key_file),
+            (OutputFormat::Text, None) => print_near_miss(found, &record),
+            _ => {
+                let record = self.public_record(record);
+                self.record(Record::NearMiss(record))
+            }
+        }
+    }
+
+    /// Progress reports are records with NDJSON, and text on stderr
+    /// otherwise, as the JSON array is only written at the end
+    pub fn progress(&mut self, record: ProgressRecord) {
+        match self.format {
+            OutputFormat::Ndjson => self.record(Record::Progress(record)),
+            _ => eprintln!("{}", format_progress(&record)),
+        }
+    }
+
+    /// Write the summary and, for JSON, all the records
+    pub fn finish(mut self, stats: &MiningStats) {
+        let mut summary = SummaryRecord::from(stats);
+        if let Some(best) = &stats.best_match {
+            let record = self.key_record(best);
+            // The best near miss may not have been reported, keep its secret too
+            if self.saved_near_miss.as_ref() != Some(&record.hex_public_key) {
+                self.save(&record, true);
+            }
+            summary.best_near_miss = Some(self.public_record(record));
+        }
+        match self.format {
+            OutputFormat::Text => print_summary(&summary),
+            _ => self.record(Record::Summary(summary)),
+        }
+        if self.format == OutputFormat::Json {
+            let json =
+                serde_json::to_string_pretty(&self.records).expect("Records can be serialized");
+            println!("{json}");
+        }
+    }
+
+    fn key_record(&self, found: &FoundKey) -> KeyRecord {
+        let mut record = KeyRecord::from(found);
+        if let (Some(encryption), Ok(secret_key)) = (&self.encryption, found.keys.secret_key()) {
+            record.ncryptsec = Some(
+                encryption
+                    .encrypt(&secret_key)
+                    .expect("Could not encrypt the secret key"),
+            );
+        }
+        record
+    }
+
+    // The record to show, without the secret parts when there is a key file
+    fn public_record(&self, record: KeyRecord) -> KeyRecord {
+        match &self.key_file {
+            Some(key_file) => record.redacted(key_file),
+            None => record,
+        }
+    }
+
+    // Append the keys to the key file. If that fails, the key file is dropped
+    // so that the secret keys are shown instead of being lost.
+    fn save(&mut self, record: &KeyRecord, near_miss: bool) {
+        let Some(key_file) = &mut self.key_file else {
+            return;
+        };
+        let entry = match self.format {
+            OutputFormat::Text => format_key_entry(record, near_miss),
+            _ => {
+                let record = if near_miss {
+                    Record::NearMiss(record.clone())
+                } else {
+                    Record::Match(record.clone())
+                };
+                let json = serde_json::to_string(&record).expect("Records can be serialized");
+                format!("{json}\n")
+            }
+        };
+        if let Err(e) = key_file.append(&entry) {
+            eprintln!(
+                "Could not save the keys to {}: {e}. Showing them instead.",
+                key_file.path().display()
+            );
+            self.key_file = None;
+        } else if near_miss {
+            self.saved_near_miss = Some(record.hex_public_key.clone());
+        }
+    }
+
+    fn record(&mut self, record: Record) {
+        match self.format {
+            OutputFormat::Ndjson => {
+                let json = serde_json::to_string(&record).expect("Records can be serialized");
+                println!("{json}");
+                std::io::stdout().flush().expect("Failed to flush stdout");
+            }
+            _ => self.records.push(record),
+        }
+    }
+}
+
+/// File the secret keys are appended to, so that they don't end up in the
+/// terminal scrollback or in logs
+pub struct KeyFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl KeyFile {
+    /// Open `path` for appending, creating it readable by its owner only.
+    /// Fails when the file already exists and other users can access it.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+            options.mode(0o600);
+            if let Ok(metadata) = std::fs::metadata(path) {
+                if metadata.permissions().mode() & 0o077 != 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "{} can be accessed by other users, restrict it with `chmod 600` or choose another file",
+                            path.display()
+                        ),
+                    ));
+                }
+            }
+        }
+        let file = options.open(path)?;
+        Ok(KeyFile {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&mut self, entry: &str) -> io::Result<()> {
+        self.file.write_all(entry.as_bytes())?;
+        self.file.sync_data()
+    }
+}
+
+// A found key in the key file when the output format is text
+fn format_key_entry(record: &KeyRecord, near_miss: bool) -> String {
+    let mut entry = String::new();
+    if near_miss {
+        entry += &format!("Near miss ({:.2}% similarity)\n", record.similarity);
+    } else {
+        entry += "Exact match\n";
+    }
+    if !record.target.is_empty() {
+        entry += &format!("Target:           {}\n", record.target);
+    }
+    if record.leading_zero_bits != 0 {
+        entry += &format!("Leading zero bits: {}\n", record.leading_zero_bits);
+    }
+    entry += &format!("Hex public key:   {}\n", record.hex_public_key);
+    if let Some(hex_secret_key) = &record.hex_secret_key {
+        entry += &format!("Hex private key:  {hex_secret_key}\n");
+    }
+    entry += &format!("Npub public key:  {}\n", record.npub);
+    if let Some(nsec) = &record.nsec {
+        entry += &format!("Nsec private key: {nsec}\n");
+    }
+    if let Some(tweak) = &record.tweak {
+        entry += &format!("Tweak:            {tweak}\n");
+    }
+    if let Some(ncryptsec) = &record.ncryptsec {
+        entry += &format!("Ncryptsec:        {ncryptsec}\n");
+    }
+    if let Some(mnemonic) = &record.mnemonic {
+        entry += &format!("Mnemonic:         {mnemonic}\n");
+    }
+    if let Some(derivation_path) = &record.derivation_path {
+        entry += &format!("Derivation path:  {derivation_path}\n");
+    }
+    entry + "\n"
+}
+
+fn print_found_key(found: &FoundKey, record: &KeyRecord, qr: bool) {
+    println!("{}", print_divider(30).bright_cyan());
+    println!("Found exact match!");
+    match &found.tweak {
+        Some(tweak) => print_tweak(found, tweak),
+        None => print_keys(
+            &found.keys,
+            found.target.clone(),
+            found.leading_zero_bits,
+            found.mnemonic.clone(),
+        )
+        .unwrap(),
+    }
+    print_derivation_path(record);
+    print_ncryptsec(record);
+    let iterations = found.iterations;
+    let iter_string = format!("{iterations}");
+    let l = iter_string.len();
+    let f = iter_string.chars().next().unwrap();
+    let elapsed = found.elapsed.as_secs();
+    println!(
+        "{} iterations (about {}x10^{} hashes) in {} seconds. Avg rate {} hashes/second",
+        iterations,
+        f,
+        l - 1,
+        elapsed,
+        iterations / max(1, elapsed)
+    );
+    if qr {
+        match &record.ncryptsec {
+            Some(ncryptsec) => print_qr_code(ncryptsec).unwrap(),
+            None => print_qr(found.keys.secret_key().unwrap()).unwrap(),
+        }
+    }
+    std::io::stdout().flush().expect("Failed to flush stdout");
+}
+
+fn print_near_miss(found: &FoundKey, record: &KeyRecord) {
+    let npub = found.keys.public_key().to_bech32().unwrap();
+    let current_prefix = npub.strip_prefix(BECH32_PREFIX).unwrap_or("");
+    println!("{}", print_divider(30).bright_yellow());
+    println!("Target:  {}", found.target);
+    println!("Found match with {:.2}% similarity:", found.similarity);
+    match &found.tweak {
+        Some(tweak) => print_tweak(found, tweak),
+        None => print_keys(
+            &found.keys,
+            current_prefix.to_string(),
+            0,
+            found.mnemonic.clone(),
+        )
+        .unwrap(),
+    }
+    print_derivation_path(record);
+    print_ncryptsec(record);
+    std::io::stdout().flush().expect("Failed to flush stdout");
+}
+
+// Split-key mining: the customer gets their secret key with `rana combine`
+fn print_tweak(found: &FoundKey, tweak: &SecretKey) {
+    if found.leading_zero_bits != 0 {
+        println!("Leading zero bits:         {}", found.leading_zero_bits);
+    }
+    println!("{}", "Found matching Nostr public key:".green());
+    println!(
+        "Hex public key: {:>66}",
+        found.keys.public_key().to_string()
+    );
+    println!(
+        "Npub public key: {:>64}",
+        found.keys.public_key().to_bech32().unwrap()
+    );
+    println!("Tweak: {:>75}", tweak.display_secret().to_string());
+    println!(
+        "The customer gets the private key with `rana combine --secret <their nsec> --tweak {} --expect {}`",
+        tweak.display_secret(),
+        found.keys.public_key().to_bech32().unwrap()
+    );
+}
+
+fn print_derivation_path(record: &KeyRecord) {
+    if let Some(derivation_path) = &record.derivation_path {
+        println!("Derivation path:  {derivation_path}");
+    }
+}
+
+fn print_ncryptsec(record: &KeyRecord) {
+    if let Some(ncryptsec) = &record.ncryptsec {
+        println!("Ncryptsec private key: {ncryptsec}");
+    }
+}
+
+fn print_saved_key(found: &FoundKey, key_file: &KeyFile) {
+    println!("{}", print_divider(30).bright_cyan());
+    println!("Found exact match!");
+    print_public_key(found, key_file);
+    println!(
+        "{} iterations in {} seconds",
+        found.iterations,
+        found.elapsed.as_secs()
+    );
+    std::io::stdout().flush().expect("Failed to flush stdout");
+}
+
+fn print_saved_near_miss(found: &FoundKey, key_file: &KeyFile) {
+    println!("{}", print_divider(30).bright_yellow());
+    println!("Target:  {}", found.target);
+    println!("Found match with {:.2}% similarity:", found.similarity);
+    print_public_key(found, key_file);
+    std::io::stdout().flush().expect("Failed to flush stdout");
+}
+
+fn print_public_key(found: &FoundKey, key_file: &KeyFile) {
+    println!(
+        "Hex public key: {:>66}",
+        found.keys.public_key().to_string()
+    );
+    println!(
+        "Npub public key: {:>64}",
+        found.keys.public_key().to_bech32().unwrap()
+    );
+    if let Some(derivation_path) = &found.derivation_path {
+        println!("Derivation path:  {derivation_path}");
+    }
+    println!(
+        "{}",
+        format!("Private key saved to {}", key_file.path().display()).green()
+    );
+}
+
+pub fn format_progress(record: &ProgressRecord) -> String {
+    let mut report = format!(
+        "[{}] {} keys, {:.0} h/s (avg {:.0} h/s; per thread:",
+        format_duration(Duration::from_secs_f64(record.elapsed_seconds)),
+        record.iterations,
+        record.rate,
+        record.average_rate
+    );
+    for thread_rate in &record.thread_rates {
+        report += &format!(" {thread_rate:.0}");
+    }
+    report += ")";
+    if let Some(leading_zero_bits) = record.best_leading_zero_bits {
+        report += &format!(", best: {leading_zero_bits} leading zero bits");
+    }
+    if let Some(best) = &record.best_near_miss {
+        report += &format!(
+            ", best near miss: {:.2}% of '{}'",
+            best.similarity, best.target
+        );
+    }
+    if let Some(chance) = record.chance {
+        report += &format!(", {chance:.1}% chance of a match by now");
+    }
+    report
+}
+
+fn print_summary(summary: &SummaryRecord) {
+    eprintln!("{}", print_divider(30).bright_cyan());
+    eprintln!("Summary:");
+    eprintln!("Iterations: {}", summary.iterations);
+    eprintln!("Elapsed: {:.1} seconds", summary.elapsed_seconds);
+    eprintln!("Avg rate: {:.0} hashes/second", summary.rate);
+    eprintln!("Matches: {}", summary.matches);
+    if let Some(best) = &summary.best_near_miss {
+        eprintln!(
+            "Best near miss: {} ({:.2}% similar to '{}')",
+            best.npub, best.similarity, best.target
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use nostr::prelude::*;
+use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind, Literal, Look};
+use regex_syntax::Parser;
+
+use crate::encoding::encode_hex;
+use crate::matcher::{MatchResult, Matcher, BECH32_PREFIX, HEX_LEN, NPUB_BODY_LEN};
+
+pub const BECH32_ALPHABET: &str = "023456789acdefghjklmnpqrstuvwxyz";
+pub const HEX_ALPHABET: &str = "0123456789abcdef";
+
+/// Representation of the public key a pattern is matched against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternFormat {
+    /// The npub, without the `npub1` prefix
+    Npub,
+    /// The hexadecimal public key
+    Hex,
+}
+
+impl PatternFormat {
+    fn alphabet(&self) -> &'static str {
+        match self {
+            PatternFormat::Npub => BECH32_ALPHABET,
+            PatternFormat::Hex => HEX_ALPHABET,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            PatternFormat::Npub => NPUB_BODY_LEN,
+            PatternFormat::Hex => HEX_LEN,
+        }
+    }
+}
+
+/// Regular expression over the npub or the hex public key, like
+/// `^npub1(rana|h0dl)[0-9]{2}` or `.*h0dl$`.
+///
+/// Npub patterns are matched against the characters after `npub1`, so a
+/// leading `npub1` in the pattern is accepted and means "right at the start".
+/// With alternatives, either all of them or none of them start with `npub1`.
+pub struct PatternMatcher {
+    pattern: String,
+    format: PatternFormat,
+    regex: Regex,
+    difficulty: f64,
+}
+
+impl PatternMatcher {
+    pub fn new(pattern: &str, format: PatternFormat) -> Result<Self> {
+        let normalized = match format {
+            PatternFormat::Npub => normalize_npub_pattern(pattern)?,
+            PatternFormat::Hex => pattern.to_string(),
+        };
+        // The regex decides which syntax is valid, its own parser gives the
+        // HIR to check the characters and estimate the difficulty
+        let regex = Regex::new(&normalized)?;
+        let hir = Parser::new().parse(&normalized)?;
+        validate(&hir, format)?;
+        let difficulty = estimate_difficulty(&hir, format);
+
+        Ok(PatternMatcher {
+            pattern: pattern.to_string(),
+            format,
+            regex,
+            difficulty,
+        })
+    }
+
+    /// Estimated number of bits of work needed to find a match, i.e. a match
+    /// is expected about every 2^difficulty keys
+    pub fn difficulty(&self) -> f64 {
+        self.difficulty
+    }
+}
+
+impl Matcher for PatternMatcher {
+    fn check(&self, pubkey: &XOnlyPublicKey, npub: &str) -> MatchResult<'_> {
+        let is_match = match self.format {
+            PatternFormat::Npub => self
+                .regex
+                .is_match(npub.strip_prefix(BECH32_PREFIX).unwrap_or("")),
+            PatternFormat::Hex => self
+                .regex
+                .is_match(encode_hex(&pubkey.serialize(), &mut [0; HEX_LEN])),
+        };
+        if is_match {
+            MatchResult::Hit {
+                target: &self.pattern,
+                leading_zero_bits: 0,
+            }
+        } else {
+            MatchResult::Miss
+        }
+    }
+
+    fn needs_npub(&self) -> bool {
+        self.format == PatternFormat::Npub
+    }
+
+    fn description(&self) -> String {
+        match self.format {
+            PatternFormat::Npub => format!("npub pattern: '{}'", self.pattern),
+            PatternFormat::Hex => format!("hex pattern: '{}'", self.pattern),
+        }
+    }
+
+    fn probability(&self) -> Option<f64> {
+        Some(2_f64.powf(-self.difficulty))
+    }
+}
+
+// `npub1` can only ever appear at the very start, so make that explicit and
+// leave a pattern over the rest of the npub. It is only stripped when every
+// alternative of the pattern starts with it, as in `^npub1abc|^npub1xyz`.
+fn normalize_npub_pattern(pattern: &str) -> Result<String> {
+    let alternatives = top_level_alternatives(pattern);
+    let stripped: Vec<Option<&str>> = alternatives
+        .iter()
+        .map(|alternative| {
+            let unanchored = alternative.strip_prefix('^').unwrap_or(alternative);
+            unanchored.strip_prefix(BECH32_PREFIX)
+        })
+        .collect();
+    if stripped.iter().all(Option::is_none) {
+        return Ok(pattern.to_string());
+    }
+    if stripped.iter().any(Option::is_none) {
+        return Err(format!(
+            "Either every alternative of the pattern '{pattern}' starts with {BECH32_PREFIX} or none does, group them as in ^{BECH32_PREFIX}(abc|xyz)"
+        )
+        .into());
+    }
+    Ok(stripped
+        .into_iter()
+        .map(|rest| format!("^{}", rest.unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join("|"))
+}
+
+// The alternatives of the pattern split at the `|` outside of any group or
+// character class
+fn top_level_alternatives(pattern: &str) -> Vec<&str> {
+    let mut alternatives = Vec::new();
+    let (mut start, mut groups, mut classes) = (0, 0usize, 0usize);
+    let mut chars = pattern.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => {
+                classes += 1;
+                // A `]` right after the opening bracket is a literal
+                if let Some((_, '^')) = chars.peek() {
+                    chars.next();
+                }
+                if let Some((_, ']')) = chars.peek() {
+                    chars.next();
+                }
+            }
+            ']' if classes > 0 => classes -= 1,
+            '(' if classes == 0 => groups += 1,
+            ')' if classes == 0 => groups = groups.saturating_sub(1),
+            '|' if classes == 0 && groups == 0 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&pattern[start..]);
+    alternatives
+}
+
+// Every literal must be part of the alphabet and every class must contain at
+// least one of its characters, otherwise the pattern could never match
+fn validate(hir: &Hir, format: PatternFormat) -> Result<()> {
+    let alphabet = format.alphabet();
+    match hir.kind() {
+        HirKind::Literal(Literal(bytes)) => {
+            let literal = std::str::from_utf8(bytes)
+                .map_err(|_| format!("The pattern cannot contain the bytes {bytes:x?}"))?;
+            if let Some(c) = literal.chars().find(|c| !alphabet.contains(*c)) {
+                return Err(format!(
+                    "The pattern can only contain characters supported by {}: {alphabet} (found '{c}')",
+                    format_name(format)
+                )
+                .into());
+            }
+        }
+        HirKind::Class(class) => {
+            if class_size(class, alphabet) == 0 {
+                return Err(format!(
+                    "The pattern contains a character class without any character supported by {}: {alphabet}",
+                    format_name(format)
+                )
+                .into());
+            }
+        }
+        HirKind::Repetition(repetition) => validate(&repetition.sub, format)?,
+        HirKind::Capture(capture) => validate(&capture.sub, format)?,
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => {
+            for hir in hirs {
+                validate(hir, format)?;
+            }
+        }
+        HirKind::Empty | HirKind::Look(_) => {}
+    }
+    Ok(())
+}
+
+fn format_name(format: PatternFormat) -> &'static str {
+    match format {
+        PatternFormat::Npub => "Bech32",
+        PatternFormat::Hex => "hexadecimal",
+    }
+}
+
+fn class_size(class: &Class, alphabet: &str) -> usize {
+    alphabet
+        .chars()
+        .filter(|c| match class {
+            Class::Unicode(class) => class
+                .iter()
+                .any(|range| range.start() <= *c && *c <= range.end()),
+            Class::Bytes(class) => class
+                .iter()
+                .any(|range| range.start() <= *c as u8 && *c as u8 <= range.end()),
+        })
+        .count()
+}
+
+// Characters of a literal, which holds a whole run of them
+fn literal_len(literal: &Literal) -> usize {
+    String::from_utf8_lossy(&literal.0).chars().count()
+}
+
+// Bits of work for the pattern to match at one given position. Repetitions
+// count with their minimum, the cheapest way for them to match.
+fn position_bits(hir: &Hir, alphabet: &str) -> f64 {
+    let size = alphabet.len() as f64;
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => 0.0,
+        HirKind::Literal(literal) => literal_len(literal) as f64 * size.log2(),
+        HirKind::Class(class) => (size / class_size(class, alphabet).max(1) as f64).log2(),
+        HirKind::Repetition(repetition) => {
+            repetition.min as f64 * position_bits(&repetition.sub, alphabet)
+        }
+        HirKind::Capture(capture) => position_bits(&capture.sub, alphabet),
+        HirKind::Concat(hirs) => hirs.iter().map(|hir| position_bits(hir, alphabet)).sum(),
+        HirKind::Alternation(hirs) => -hirs
+            .iter()
+            .map(|hir| 2_f64.powf(-position_bits(hir, alphabet)))
+            .sum::<f64>()
+            .log2(),
+    }
+}
+
+// Minimum number of characters the pattern matches
+fn min_len(hir: &Hir) -> usize {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => 0,
+        HirKind::Literal(literal) => literal_len(literal),
+        HirKind::Class(_) => 1,
+        HirKind::Repetition(repetition) => repetition.min as usize * min_len(&repetition.sub),
+        HirKind::Capture(capture) => min_len(&capture.sub),
+        HirKind::Concat(hirs) => hirs.iter().map(min_len).sum(),
+        HirKind::Alternation(hirs) => hirs.iter().map(min_len).min().unwrap_or(0),
+    }
+}
+
+fn estimate_difficulty(hir: &Hir, format: PatternFormat) -> f64 {
+    let bits = position_bits(hir, format.alphabet());
+    // An unanchored pattern gets a chance at every position it fits in
+    let properties = hir.properties();
+    let positions = if properties.look_set_prefix().contains(Look::Start)
+        || properties.look_set_suffix().contains(Look::End)
+    {
+        1
+    } else {
+        (format.len() + 1).saturating_sub(min_len(hir)).max(1)
+    };
+    (bits - (positions as f64).log2()).max(0.0)
+}
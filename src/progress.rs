@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::estimate::Estimate;
+use crate::matcher::Matcher;
+use crate::miner::{FoundKey, Miner, MiningStats};
+use crate::output::{format_progress, KeyRecord, Output, ProgressRecord};
+
+/// Periodic report of a running search, on stderr or as NDJSON records
+pub struct Progress {
+    interval: Duration,
+    matcher: Arc<dyn Matcher>,
+    last_report: Instant,
+    // Iterations of every thread at the last report
+    last_iterations: Vec<u64>,
+    best_leading_zero_bits: u8,
+}
+
+impl Progress {
+    pub fn new(interval: Duration, matcher: Arc<dyn Matcher>, threads: usize) -> Self {
+        Progress {
+            interval,
+            matcher,
+            last_report: Instant::now(),
+            last_iterations: vec![0; threads],
+            best_leading_zero_bits: 0,
+        }
+    }
+
+    pub fn found(&mut self, found: &FoundKey) {
+        self.best_leading_zero_bits = self.best_leading_zero_bits.max(found.leading_zero_bits);
+    }
+
+    /// Report the progress if the interval passed since the last one
+    pub fn update(&mut self, miner: &Miner, output: &mut Output) {
+        let since_last = self.last_report.elapsed();
+        if since_last < self.interval {
+            return;
+        }
+        let iterations = miner.thread_iterations();
+        let thread_rates: Vec<f64> = iterations
+            .iter()
+            .zip(&self.last_iterations)
+            .map(|(now, last)| (now - last) as f64 / since_last.as_secs_f64())
+            .collect();
+        output.progress(self.record(&miner.stats(), thread_rates));
+        self.last_report = Instant::now();
+        self.last_iterations = iterations;
+    }
+
+    pub fn record(&self, stats: &MiningStats, thread_rates: Vec<f64>) -> ProgressRecord {
+        let average_rate = stats.iterations as f64 / stats.elapsed.as_secs_f64().max(0.001);
+        ProgressRecord {
+            iterations: stats.iterations,
+            elapsed_seconds: stats.elapsed.as_secs_f64(),
+            rate: thread_rates.iter().sum(),
+            average_rate,
+            thread_rates,
+            best_leading_zero_bits: (self.best_leading_zero_bits > 0)
+                .then_some(self.best_leading_zero_bits),
+            best_near_miss: stats
+                .best_match
+                .as_ref()
+                .map(|best| KeyRecord::from(best).public()),
+            chance: self.matcher.probability().map(|probability| {
+                let estimate = Estimate {
+                    probability,
+                    rate: average_rate,
+                };
+                estimate.chance_after(stats.iterations) * 100.0
+            }),
+        }
+    }
+
+    /// The report as printed on stderr with the text output
+    pub fn report(&self, stats: &MiningStats, thread_rates: &[f64]) -> String {
+        format_progress(&self.record(stats, thread_rates.to_vec()))
+    }
+}
@@ -0,0 +1,66 @@
+//! Secrets typed on the terminal instead of given as arguments, which would
+//! land in the shell history
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Read a line from stdin. On a terminal, `prompt` is shown on stderr and
+/// what is typed is not echoed.
+pub fn read_secret(prompt: &str) -> io::Result<String> {
+    let stdin = io::stdin();
+    let mut line = String::new();
+    if stdin.is_terminal() {
+        eprint!("{prompt}");
+        io::stderr().flush()?;
+        let hidden = HiddenInput::new();
+        stdin.lock().read_line(&mut line)?;
+        drop(hidden);
+        eprintln!();
+    } else {
+        stdin.lock().read_line(&mut line)?;
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// Turns off the echo of the terminal until dropped
+#[cfg(unix)]
+struct HiddenInput(Option<libc::termios>);
+
+#[cfg(unix)]
+impl HiddenInput {
+    fn new() -> Self {
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return HiddenInput(None);
+            }
+            let original = termios;
+            termios.c_lflag &= !libc::ECHO;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return HiddenInput(None);
+            }
+            HiddenInput(Some(original))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for HiddenInput {
+    fn drop(&mut self) {
+        if let Some(original) = &self.0 {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+}
+
+/// The echo is only turned off on unix, elsewhere the secret is visible
+#[cfg(not(unix))]
+struct HiddenInput;
+
+#[cfg(not(unix))]
+impl HiddenInput {
+    fn new() -> Self {
+        HiddenInput
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch SIGINT (Ctrl-C) and SIGTERM so that a search can be stopped
+/// gracefully. Check [`interrupted`] to know whether one was received.
+#[cfg(unix)]
+pub fn handle_interrupts() {
+    extern "C" fn handler(_signal: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    let handler = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Signals are only caught on unix, elsewhere the process is just killed
+#[cfg(not(unix))]
+pub fn handle_interrupts() {}
+
+/// Act as if SIGINT was received
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,70 @@
+//! Split-key mining: the customer only shares their public key `P`, rana
+//! finds a tweak `k` such that `P + k·G` matches the target, and the customer
+//! adds `k` to their secret key. Nobody else ever knows the vanity secret key.
+
+use std::str::FromStr;
+
+use nostr::prelude::*;
+use nostr::secp256k1::{Parity, PublicKey};
+
+use crate::cli::CombineArgs;
+
+/// The point to start the search from. Nostr public keys are x-only, so it
+/// is the one with an even `y`.
+pub fn base_point(public_key: &XOnlyPublicKey) -> PublicKey {
+    PublicKey::from_x_only_public_key(*public_key, Parity::Even)
+}
+
+/// Secret key of the vanity public key found for the customer. A secret key
+/// giving an odd `y` is negated first, like in BIP-340.
+pub fn combine(secret_key: &SecretKey, tweak: &SecretKey) -> Result<SecretKey> {
+    let (_, parity) = secret_key.x_only_public_key(SECP256K1);
+    let secret_key = match parity {
+        Parity::Even => *secret_key,
+        Parity::Odd => secret_key.negate(),
+    };
+    Ok(secret_key.add_tweak(&Scalar::from(*tweak))?)
+}
+
+/// Parse an npub or a hex public key
+pub fn parse_public_key(public_key: &str) -> Result<XOnlyPublicKey> {
+    if public_key.starts_with("npub1") {
+        Ok(XOnlyPublicKey::from_bech32(public_key)?)
+    } else {
+        Ok(XOnlyPublicKey::from_str(public_key)?)
+    }
+}
+
+/// Parse an nsec or a hex secret key
+pub fn parse_secret_key(secret_key: &str) -> Result<SecretKey> {
+    if secret_key.starts_with("nsec1") {
+        Ok(SecretKey::from_bech32(secret_key)?)
+    } else {
+        Ok(SecretKey::from_str(secret_key)?)
+    }
+}
+
+/// `rana combine`: add the mined tweak to the secret key of the customer
+pub fn handle_combine(args: &CombineArgs) -> Result<()> {
+    let secret_key = parse_secret_key(&args.secret_key)?;
+    let tweak = SecretKey::from_str(&args.tweak)?;
+    let keys = Keys::new(combine(&secret_key, &tweak)?);
+    let npub = keys.public_key().to_bech32()?;
+
+    if let Some(expected) = &args.expected {
+        if parse_public_key(expected)? != keys.public_key() {
+            return Err(format!(
+                "The combined public key {npub} is not the expected one, check the secret key and the tweak"
+            )
+            .into());
+        }
+        eprintln!("The combined public key is the expected one.");
+    }
+
+    println!(
+        "Public key: {}\nPrivate key: {}",
+        npub,
+        keys.secret_key()?.to_bech32()?
+    );
+    Ok(())
+}
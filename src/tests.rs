@@ -1,10 +1,945 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::cli::CLIArgs;
+    use std::str::FromStr;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use clap::Parser;
+    use nostr::bip39::Language;
+    use nostr::bitcoin::util::bip32::{ChildNumber, DerivationPath};
+    use nostr::prelude::*;
+    use unicode_normalization::UnicodeNormalization;
+
+    use crate::checkpoint::Checkpoint;
+    use crate::cli::{check_args, parse_accounts, parse_duration, CLIArgs, Command, MineArgs};
+    use crate::encoding::{encode_hex, encode_npub, BitPrefix, NPUB_LEN};
+    use crate::error::{RanaError, UnknownWord};
+    use crate::estimate::Estimate;
+    use crate::event::{mine_event, EventTemplate};
+    use crate::matcher::{
+        HexContainsMatcher, HexPrefixMatcher, LeadingZeroBitsMatcher, MatchResult, Matcher,
+        NpubContainsMatcher, NpubPrefixMatcher, NpubPrefixSuffixMatcher, NpubSuffixMatcher,
+        HEX_LEN,
+    };
+    use crate::miner::{
+        DerivationScan, FoundKey, KeySource, Miner, MiningEvent, MiningJob, MiningStats,
+    };
+    use crate::mnemonic::{derive_keys, master_key, parse_mnemonic, MnemonicLanguage};
+    use crate::nip49::{self, KeySecurity};
+    use crate::output::{KeyFile, Record};
+    use crate::pattern::{PatternFormat, PatternMatcher};
+    use crate::progress::Progress;
+    use crate::signal;
+    use crate::splitkey;
+    use crate::utils::get_leading_zero_bits;
 
     #[test]
     fn cli_tests() {
         use clap::CommandFactory;
         CLIArgs::command().debug_assert();
     }
+
+    // The arguments of `rana mine`
+    fn mine_args(args: &[&str]) -> MineArgs {
+        let args = ["rana", "mine"].iter().chain(args);
+        match CLIArgs::parse_from(args).command {
+            Some(Command::Mine(args)) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn subcommands() {
+        // The flags of the previous versions still work without `mine`
+        let cli = CLIArgs::parse_from(["rana", "-n", "rana", "-c", "1"]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.mine.vanity_npub_prefixes(), vec!["rana"]);
+        assert_eq!(
+            mine_args(&["-n", "rana"]).vanity_npub_prefixes(),
+            vec!["rana"]
+        );
+
+        assert!(CLIArgs::try_parse_from(["rana", "-n", "rana", "mine"]).is_err());
+        assert!(CLIArgs::try_parse_from(["rana", "mine", "-r", "words"]).is_err());
+        assert!(matches!(
+            CLIArgs::parse_from(["rana", "mnemonic", "restore", "words", "-p", "x"]).command,
+            Some(Command::Mnemonic(_))
+        ));
+    }
+
+    #[test]
+    fn argument_errors() {
+        let check = |args: &[&str]| check_args(&mine_args(args));
+        assert_eq!(check(&["-n", "rana"]), Ok(()));
+        assert_eq!(
+            check(&["-n", "h0dl,rana!"]),
+            Err(RanaError::InvalidBech32 {
+                option: "-n, --vanity-n-prefix",
+                target: "rana!".to_string(),
+                position: 5,
+                character: '!',
+            })
+        );
+        assert_eq!(
+            check(&["-v", "dead", "-n", "rana"]),
+            Err(RanaError::MultipleRequirements)
+        );
+        assert!(matches!(
+            check(&["--vanity-contains", "beef", "--within", "3"]),
+            Err(RanaError::TargetTooLong { max: 3, .. })
+        ));
+        assert!(matches!(
+            check(&["-c", "0"]),
+            Err(RanaError::InvalidCores { requested: 0, .. })
+        ));
+        assert!(matches!(
+            check(&["-n", "rana", "--qr", "--output-format", "ndjson"]),
+            Err(RanaError::Conflict { option: "--qr", .. })
+        ));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(2 * 24 * 3600)));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+
+    #[test]
+    fn miner_stops_after_max_matches() {
+        let job = MiningJob::builder()
+            .matcher(LeadingZeroBitsMatcher::new(2, false))
+            .threads(2)
+            .max_matches(3)
+            .build();
+
+        let mut found = Vec::new();
+        let stats = Miner::run(job, |event| {
+            if let MiningEvent::Found(key) = event {
+                found.push(key);
+            }
+        });
+
+        assert_eq!(found.len(), 3);
+        assert_eq!(stats.matches, 3);
+        for key in found {
+            assert!(get_leading_zero_bits(&key.keys.public_key().serialize()) > 2);
+        }
+    }
+
+    #[test]
+    fn miner_stops_after_max_iterations() {
+        let job = MiningJob::builder()
+            .matcher(HexPrefixMatcher::new("ffffffffffffffff"))
+            .threads(2)
+            .max_iterations(1000)
+            .build();
+
+        let stats = Miner::run(job, |_| {});
+        assert!(stats.iterations >= 1000);
+        assert_eq!(stats.matches, 0);
+    }
+
+    #[test]
+    fn miner_stops_on_time_with_slow_key_sources() {
+        // Far fewer keys than the iterations between two limit checks
+        let job = MiningJob::builder()
+            .matcher(HexPrefixMatcher::new("ffffffffffffffff"))
+            .key_source(KeySource::Mnemonic {
+                word_count: 24,
+                passphrase: String::new(),
+                language: Language::English,
+            })
+            .threads(1)
+            .timeout(Duration::from_millis(200))
+            .build();
+
+        let stats = Miner::run(job, |_| {});
+        assert!(stats.iterations < 1000);
+        assert!(stats.elapsed < Duration::from_secs(2));
+
+        // The timeout is the one of this run, even after hours of others
+        let previous = MiningStats {
+            elapsed: Duration::from_secs(7200),
+            ..Default::default()
+        };
+        let job = MiningJob::builder()
+            .matcher(HexPrefixMatcher::new("ffffffffffffffff"))
+            .threads(1)
+            .timeout(Duration::from_millis(200))
+            .resume(previous)
+            .build();
+        let stats = Miner::run(job, |_| {});
+        assert!(stats.elapsed >= Duration::from_secs(7200) + Duration::from_millis(200));
+    }
+
+    #[test]
+    fn miner_stops_on_interrupts_between_events() {
+        // Every key is reported, so waiting for an event never times out
+        let job = MiningJob::builder()
+            .matcher(HexPrefixMatcher::new("ffffffffffffffff"))
+            .threads(1)
+            .report_misses(true)
+            .build();
+        let miner = Miner::start(job);
+        let started = Instant::now();
+        let mut events = 0;
+        let mut stopped = false;
+        loop {
+            stopped |= miner.stop_if_interrupted();
+            match miner.events().recv_timeout(Duration::from_millis(200)) {
+                Ok(_) => events += 1,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if events == 100 {
+                signal::interrupt();
+            }
+            assert!(started.elapsed() < Duration::from_secs(10));
+        }
+        assert!(stopped);
+        assert!(miner.join().iterations >= 100);
+    }
+
+    struct EvenMatcher;
+
+    impl Matcher for EvenMatcher {
+        fn check(&self, pubkey: &XOnlyPublicKey, _npub: &str) -> MatchResult<'_> {
+            if pubkey.serialize()[31] & 1 == 0 {
+                MatchResult::Hit {
+                    target: "even",
+                    leading_zero_bits: 0,
+                }
+            } else {
+                MatchResult::Miss
+            }
+        }
+
+        fn needs_npub(&self) -> bool {
+            false
+        }
+
+        fn description(&self) -> String {
+            "even public key".to_string()
+        }
+    }
+
+    #[test]
+    fn miner_uses_custom_matcher() {
+        let job = MiningJob::builder()
+            .matcher(EvenMatcher)
+            .threads(1)
+            .max_matches(5)
+            .build();
+
+        Miner::run(job, |event| {
+            if let MiningEvent::Found(found) = event {
+                assert_eq!(found.target, "even");
+                assert_eq!(found.keys.public_key().serialize()[31] & 1, 0);
+            }
+        });
+    }
+
+    #[test]
+    fn prefix_suffix_matcher_requires_both() {
+        let npub = "npub1raulp7906e5e9zrevn9a4sx4u3feq5682xjnhhakhay7fwuyeewqrly00g";
+        let pubkey = XOnlyPublicKey::from_bech32(npub).unwrap();
+
+        let matcher = NpubPrefixSuffixMatcher::new(vec!["ra".to_string()], vec!["00g".to_string()]);
+        assert_eq!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Hit {
+                target: "ra...00g",
+                leading_zero_bits: 0
+            }
+        );
+
+        let matcher = NpubPrefixSuffixMatcher::new(
+            vec!["ra".to_string(), "rana".to_string()],
+            vec!["end".to_string()],
+        );
+        assert!(matches!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Partial {
+                target: "ra...end",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn pattern_matcher() {
+        let npub = "npub1raulp7906e5e9zrevn9a4sx4u3feq5682xjnhhakhay7fwuyeewqrly00g";
+        let pubkey = XOnlyPublicKey::from_bech32(npub).unwrap();
+
+        let matcher = PatternMatcher::new("^npub1(rana|rau)[a-z]", PatternFormat::Npub).unwrap();
+        assert!(matches!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Hit { .. }
+        ));
+        let matcher = PatternMatcher::new(".*y0{2}g$", PatternFormat::Npub).unwrap();
+        assert!(matches!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Hit { .. }
+        ));
+        let matcher = PatternMatcher::new("^1f7[0-8]", PatternFormat::Hex).unwrap();
+        assert_eq!(matcher.check(&pubkey, ""), MatchResult::Miss);
+
+        // 'b' is not part of the bech32 alphabet
+        assert!(PatternMatcher::new("rab", PatternFormat::Npub).is_err());
+        assert!(PatternMatcher::new("^[bio]", PatternFormat::Npub).is_err());
+        assert!(PatternMatcher::new("^xyz", PatternFormat::Hex).is_err());
+
+        // 4 characters of 5 bits each
+        let matcher = PatternMatcher::new("^npub1rana", PatternFormat::Npub).unwrap();
+        assert_eq!(matcher.difficulty(), 20.0);
+        // Two alternatives halve the work, a class of 8 characters is 2 bits
+        let matcher = PatternMatcher::new("^(rana|h0dl)[2-9]", PatternFormat::Npub).unwrap();
+        assert_eq!(matcher.difficulty(), 21.0);
+        let matcher = PatternMatcher::new("^dead", PatternFormat::Hex).unwrap();
+        assert_eq!(matcher.difficulty(), 16.0);
+        // A run of literal characters anywhere, like --vanity-n-contains
+        let matcher = PatternMatcher::new("rana", PatternFormat::Npub).unwrap();
+        assert!((matcher.difficulty() - (20.0 - 55_f64.log2())).abs() < 1e-9);
+
+        // npub1 is stripped from every alternative, or from none
+        let matcher = PatternMatcher::new("^npub1xyz|^npub1rau", PatternFormat::Npub).unwrap();
+        assert!(matches!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Hit { .. }
+        ));
+        // The | of a class is not an alternative
+        let matcher = PatternMatcher::new("^npub1r[x|a]u", PatternFormat::Npub).unwrap();
+        assert!(matches!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Hit { .. }
+        ));
+        assert!(PatternMatcher::new("^npub1abc|xyz", PatternFormat::Npub).is_err());
+        assert!(PatternMatcher::new("^npub1|xyz", PatternFormat::Npub).is_err());
+    }
+
+    #[test]
+    fn contains_matchers() {
+        let npub = "npub1raulp7906e5e9zrevn9a4sx4u3feq5682xjnhhakhay7fwuyeewqrly00g";
+        let pubkey = XOnlyPublicKey::from_bech32(npub).unwrap();
+
+        let matcher = NpubContainsMatcher::new(vec!["hakha".to_string()], None);
+        assert!(matches!(
+            matcher.check(&pubkey, npub),
+            MatchResult::Hit {
+                target: "hakha",
+                ..
+            }
+        ));
+        let matcher = NpubContainsMatcher::new(vec!["hakha".to_string()], Some(10));
+        assert_eq!(matcher.check(&pubkey, npub), MatchResult::Miss);
+
+        let matcher = HexContainsMatcher::new(vec!["f0f8".to_string()], Some(10));
+        assert!(matches!(
+            matcher.check(&pubkey, ""),
+            MatchResult::Hit { target: "f0f8", .. }
+        ));
+
+        // 4 characters anywhere in the npub are much cheaper than a prefix
+        let matcher = NpubContainsMatcher::new(vec!["rana".to_string()], None);
+        assert!((matcher.difficulty() - (20.0 - 55_f64.log2())).abs() < 1e-9);
+        let matcher = NpubContainsMatcher::new(vec!["rana".to_string()], Some(4));
+        assert_eq!(matcher.difficulty(), 20.0);
+    }
+
+    #[test]
+    fn estimates() {
+        let probability = |matcher: &dyn Matcher| matcher.probability().unwrap();
+        assert_eq!(probability(&HexPrefixMatcher::new("dead")), 2_f64.powi(-16));
+        assert_eq!(
+            probability(&LeadingZeroBitsMatcher::new(10, true)),
+            2_f64.powi(-11)
+        );
+
+        // Bech32 characters hold 5 bits, and "rana" already covers "ranah"
+        let prefixes = vec!["rana".to_string(), "h0dl".to_string(), "ranah".to_string()];
+        assert_eq!(
+            probability(&NpubPrefixMatcher::new(prefixes)),
+            2.0 * 2_f64.powi(-20)
+        );
+        // The last data character before the 6 of the checksum holds 1 bit
+        assert_eq!(
+            probability(&NpubSuffixMatcher::new(vec!["sqqqqqq".to_string()])),
+            2_f64.powi(-31)
+        );
+        assert_eq!(
+            probability(&NpubSuffixMatcher::new(vec!["pqqqqqq".to_string()])),
+            0.0
+        );
+        assert_eq!(
+            probability(&NpubPrefixSuffixMatcher::new(
+                vec!["ra".to_string()],
+                vec!["na".to_string(), "xna".to_string()]
+            )),
+            2_f64.powi(-20)
+        );
+
+        let estimate = Estimate {
+            probability: 2_f64.powi(-20),
+            rate: 1000.0,
+        };
+        assert_eq!(estimate.expected_iterations(), 1048576.0);
+        let half = estimate.iterations_for(0.5);
+        assert!((half - 2_f64.ln() * 1048576.0).abs() < 1.0);
+        assert!(estimate.time_for(0.99).unwrap() > estimate.time_for(0.9).unwrap());
+        assert_eq!(estimate.chance_after(0), 0.0);
+        assert!((estimate.chance_after(1048576) - (1.0 - (-1_f64).exp())).abs() < 1e-6);
+    }
+
+    #[test]
+    fn progress_reports() {
+        let stats = MiningStats {
+            iterations: 2048,
+            matches: 0,
+            elapsed: Duration::from_secs(2),
+            best_match: None,
+        };
+        // A difficulty of 10 matches one key in 2^11, 63.2% after 2^11 keys
+        let progress = Progress::new(
+            Duration::from_secs(1),
+            Arc::new(LeadingZeroBitsMatcher::new(10, true)),
+            2,
+        );
+        let report = progress.report(&stats, &[600.0, 424.4]);
+        assert_eq!(
+            report,
+            "[2.0 seconds] 2048 keys, 1024 h/s (avg 1024 h/s; per thread: 600 424), \
+             63.2% chance of a match by now"
+        );
+
+        // The chance stays the one of the target however far the scaling went
+        let progress = Progress::new(
+            Duration::from_secs(1),
+            Arc::new(LeadingZeroBitsMatcher::new(10, true).resume_from(30)),
+            2,
+        );
+        assert_eq!(progress.report(&stats, &[600.0, 424.4]), report);
+
+        // The same figures as an NDJSON record
+        let record = Record::Progress(progress.record(&stats, vec![600.0, 424.4]));
+        let json = serde_json::to_value(record).unwrap();
+        assert_eq!(json["type"], "progress");
+        assert_eq!(json["iterations"], 2048);
+        assert_eq!(json["average_rate"], 1024.0);
+        assert_eq!(json["thread_rates"], serde_json::json!([600.0, 424.4]));
+        assert!((json["chance"].as_f64().unwrap() - 63.22).abs() < 0.01);
+        assert!(json["best_near_miss"].is_null());
+    }
+
+    #[test]
+    fn incremental_key_source_recovers_secret_keys() {
+        let job = MiningJob::builder()
+            .matcher(HexPrefixMatcher::new("a"))
+            .key_source(KeySource::Incremental)
+            .threads(2)
+            .max_matches(5)
+            .build();
+
+        let mut found = 0;
+        Miner::run(job, |event| {
+            if let MiningEvent::Found(key) = event {
+                // The public key is derived again from the recomputed secret key
+                let keys = Keys::new(key.keys.secret_key().unwrap());
+                assert!(keys.public_key().to_string().starts_with('a'));
+                found += 1;
+            }
+        });
+        assert_eq!(found, 5);
+    }
+
+    #[test]
+    fn compiled_prefixes() {
+        let keys = Keys::generate();
+        let key = keys.public_key().serialize();
+        let mut buf = [0u8; NPUB_LEN];
+        let npub = encode_npub(&key, &mut buf);
+        assert_eq!(npub, keys.public_key().to_bech32().unwrap());
+
+        let body = npub.strip_prefix("npub1").unwrap();
+        for len in [1, 2, 7, 51, 52] {
+            let prefix = BitPrefix::from_npub(&body[..len]).unwrap();
+            assert!(prefix.matches(&key));
+            assert_eq!(prefix.matching_bits(&key), (len * 5).min(256));
+        }
+        // The checksum cannot be compiled, even a character of zeros that
+        // would pass for padding
+        assert!(BitPrefix::from_npub(&body[..53]).is_none());
+        assert!(BitPrefix::from_npub(&format!("{}q", &body[..52])).is_none());
+
+        let hex = keys.public_key().to_string();
+        assert_eq!(encode_hex(&key, &mut [0; HEX_LEN]), hex);
+        for len in [1, 3, 64] {
+            assert!(BitPrefix::from_hex(&hex[..len]).unwrap().matches(&key));
+        }
+        let first_bits = format!("{:08b}{:08b}", key[0], key[1]);
+        let prefix = BitPrefix::from_binary(&first_bits[..11]).unwrap();
+        assert!(prefix.matches(&key));
+        let flipped = if first_bits.as_bytes()[10] == b'0' {
+            '1'
+        } else {
+            '0'
+        };
+        let prefix = BitPrefix::from_binary(&format!("{}{flipped}", &first_bits[..10])).unwrap();
+        assert!(!prefix.matches(&key));
+        assert_eq!(prefix.matching_bits(&key), 10);
+    }
+
+    #[test]
+    fn json_records() {
+        let secret_key =
+            SecretKey::from_str("3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683")
+                .unwrap();
+        let found = FoundKey {
+            keys: Keys::new(secret_key),
+            mnemonic: None,
+            tweak: None,
+            derivation_path: None,
+            target: "rana".to_string(),
+            leading_zero_bits: 0,
+            similarity: 100.0,
+            iterations: 42,
+            elapsed: Duration::from_millis(1500),
+        };
+
+        let json = serde_json::to_value(Record::Match((&found).into())).unwrap();
+        assert_eq!(json["type"], "match");
+        assert_eq!(json["target"], "rana");
+        assert_eq!(json["iterations"], 42);
+        assert_eq!(json["elapsed_seconds"], 1.5);
+        assert_eq!(json["npub"], found.keys.public_key().to_bech32().unwrap());
+        assert_eq!(
+            json["hex_secret_key"],
+            secret_key.display_secret().to_string()
+        );
+        assert!(json["mnemonic"].is_null());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("rana-keys-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let key_file = KeyFile::open(&path).unwrap();
+        let mode = std::fs::metadata(key_file.path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(KeyFile::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ncryptsec() {
+        // Test vector of NIP-49
+        let (secret_key, key_security) = nip49::decrypt(
+            "ncryptsec1qgg9947rlpvqu76pj5ecreduf9jxhselq2nae2kghhvd5g7dgjtcxfqtd67p9m0w57lspw8gsq6yphnm8623nsl8xn9j4jdzz84zm3frztj3z7s35vpzmqf6ksu8r89qk5z2zxfmu5gv8th8wclt0h4p",
+            "nostr",
+        )
+        .unwrap();
+        assert_eq!(
+            secret_key.display_secret().to_string(),
+            "3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683"
+        );
+        assert_eq!(key_security, KeySecurity::Insecure);
+
+        let ncryptsec = nip49::encrypt(&secret_key, "ÅΩẛ̣", 4, KeySecurity::Secure).unwrap();
+        assert!(ncryptsec.starts_with("ncryptsec1"));
+        // The password is NFKC normalized
+        let decrypted = nip49::decrypt(&ncryptsec, "\u{212B}\u{2126}\u{1E9B}\u{0323}").unwrap();
+        assert_eq!(decrypted, (secret_key, KeySecurity::Secure));
+        assert!(nip49::decrypt(&ncryptsec, "wrong").is_err());
+    }
+
+    #[test]
+    fn resumed_jobs_add_up() {
+        let near_miss = first_match(KeySource::Random, 42, 1);
+        let mut checkpoint = Checkpoint::new(&mine_args(&["-n", "aa"]), 8);
+        checkpoint.update(&MiningStats {
+            iterations: 5000,
+            matches: 2,
+            elapsed: Duration::from_secs(60),
+            best_match: Some(near_miss.clone()),
+        });
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        // Only the public key of the best near miss is saved
+        let secret_key = near_miss.keys.secret_key().unwrap();
+        assert!(!json.contains(&secret_key.display_secret().to_string()));
+        let checkpoint: Checkpoint = serde_json::from_str(&json).unwrap();
+        let best = checkpoint.stats().unwrap().best_match.unwrap();
+        assert_eq!(best.keys.public_key(), near_miss.keys.public_key());
+        assert!(best.keys.secret_key().is_err());
+
+        let mut args = mine_args(&["--count", "3"]);
+        checkpoint.resume(&mut args).unwrap();
+        assert_eq!(args.vanity_npub_prefixes(), vec!["aa"]);
+        assert!(checkpoint.resume(&mut mine_args(&["-n", "bb"])).is_err());
+
+        let job = MiningJob::builder()
+            .matcher(LeadingZeroBitsMatcher::new(2, false))
+            .threads(1)
+            .max_matches(args.count.unwrap())
+            .resume(checkpoint.stats().unwrap())
+            .build();
+        let mut found = 0;
+        let stats = Miner::run(job, |event| {
+            if let MiningEvent::Found(key) = event {
+                assert!(key.iterations > 5000);
+                assert!(key.elapsed >= Duration::from_secs(60));
+                found += 1;
+            }
+        });
+        assert_eq!(found, 1);
+        assert_eq!(stats.matches, 3);
+        assert!(stats.iterations > 5000);
+
+        // The passphrase is not stored in any form, only that it must be given
+        let args = mine_args(&["-n", "aa", "-g", "12", "--language", "spanish", "-p", "x"]);
+        let checkpoint = Checkpoint::new(&args, 8);
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        assert!(!json.contains("\"x\""));
+        assert!(json.contains("\"passphrase\":true"));
+        assert!(checkpoint.resume(&mut mine_args(&[])).is_err());
+        let mut args = mine_args(&["-p", "x"]);
+        checkpoint.resume(&mut args).unwrap();
+        assert_eq!(args.language, Some(MnemonicLanguage::Spanish));
+        let args = &["-n", "aa", "-g", "12", "-p", "x"];
+        assert!(checkpoint.resume(&mut mine_args(args)).is_err());
+    }
+
+    fn first_match(key_source: KeySource, seed: u64, threads: usize) -> FoundKey {
+        let job = MiningJob::builder()
+            .matcher(HexPrefixMatcher::new("ab"))
+            .key_source(key_source)
+            .seed(seed)
+            .threads(threads)
+            .max_matches(1)
+            .build();
+        let mut found = None;
+        Miner::run(job, |event| {
+            if let MiningEvent::Found(key) = event {
+                found = Some(key);
+            }
+        });
+        found.unwrap()
+    }
+
+    #[test]
+    fn seeded_searches_are_reproducible() {
+        let found = first_match(KeySource::Random, 42, 1);
+        assert_eq!(
+            found.keys.public_key().to_string(),
+            "abbed7aa88cd0114b96fb9910c4691ddf8217394a1a0b2208d53ab7d83801b27"
+        );
+        assert_eq!(
+            found
+                .keys
+                .secret_key()
+                .unwrap()
+                .display_secret()
+                .to_string(),
+            "b0513b666862fc52888aaba651b2ad1c437cc12fc61fda7f0bca89822747b7a5"
+        );
+        assert_eq!(found.iterations, 26);
+
+        let found = first_match(KeySource::Incremental, 42, 1);
+        assert_eq!(
+            found.keys.public_key().to_string(),
+            "abacdb93401e05619e4d97de0911047c108471814fd2a0635df585c3c39b5eca"
+        );
+        assert_eq!(found.iterations, 32);
+
+        // Other threads search other keys, and the seed changes everything
+        let keys = KeySource::Random.stream(Some(42), 0, 2).next_public_key();
+        assert_ne!(
+            keys,
+            KeySource::Random.stream(Some(42), 1, 2).next_public_key()
+        );
+        assert_ne!(
+            keys,
+            KeySource::Random.stream(Some(43), 0, 2).next_public_key()
+        );
+        assert_eq!(
+            keys,
+            KeySource::Random.stream(Some(42), 0, 2).next_public_key()
+        );
+    }
+
+    // Matches a key out of 256, and partially matches the others by their
+    // first byte
+    struct ByteMatcher;
+
+    impl Matcher for ByteMatcher {
+        fn check(&self, pubkey: &XOnlyPublicKey, _npub: &str) -> MatchResult<'_> {
+            let key = pubkey.serialize();
+            if key[31] == 0 {
+                MatchResult::Hit {
+                    target: "byte",
+                    leading_zero_bits: 0,
+                }
+            } else {
+                MatchResult::Partial {
+                    target: "byte",
+                    similarity: key[0] as f64 / 2.56,
+                }
+            }
+        }
+
+        fn needs_npub(&self) -> bool {
+            false
+        }
+
+        fn description(&self) -> String {
+            "last byte of zero".to_string()
+        }
+    }
+
+    #[test]
+    fn thread_results_are_aggregated() {
+        let threads = 3;
+        let job = MiningJob::builder()
+            .matcher(ByteMatcher)
+            .key_source(KeySource::Incremental)
+            .seed(7)
+            .threads(threads)
+            .max_iterations(3000)
+            .build();
+        let miner = Miner::start(job);
+        let found = miner
+            .events()
+            .iter()
+            .filter(|event| matches!(event, MiningEvent::Found(_)))
+            .count() as u64;
+        let thread_iterations = miner.thread_iterations();
+        let stats = miner.join();
+        assert_eq!(thread_iterations.iter().sum::<u64>(), stats.iterations);
+
+        // Replay the keys every thread tried
+        let (mut matches, mut best) = (0, 0.0_f64);
+        for (index, iterations) in thread_iterations.into_iter().enumerate() {
+            let mut stream = KeySource::Incremental.stream(Some(7), index, threads);
+            for _ in 0..iterations {
+                match ByteMatcher.check(&stream.next_public_key().unwrap(), "") {
+                    MatchResult::Hit { .. } => matches += 1,
+                    MatchResult::Partial { similarity, .. } => best = best.max(similarity),
+                    MatchResult::Miss => {}
+                }
+            }
+        }
+        assert_eq!(stats.matches, matches);
+        assert_eq!(found, matches);
+        assert_eq!(stats.best_match.unwrap().similarity, best);
+    }
+
+    #[test]
+    fn split_keys_combine_to_the_found_key() {
+        // Customer keys with an even and an odd y
+        for customer in [
+            "3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683",
+            "0000000000000000000000000000000000000000000000000000000000000006",
+        ] {
+            let customer = Keys::new(SecretKey::from_str(customer).unwrap());
+            let found = first_match(
+                KeySource::SplitKey {
+                    base: customer.public_key(),
+                },
+                42,
+                1,
+            );
+            assert!(found.keys.secret_key().is_err());
+
+            let secret_key =
+                splitkey::combine(&customer.secret_key().unwrap(), &found.tweak.unwrap()).unwrap();
+            assert_eq!(Keys::new(secret_key).public_key(), found.keys.public_key());
+            assert!(found.keys.public_key().to_string().starts_with("ab"));
+        }
+    }
+
+    #[test]
+    fn event_proof_of_work() {
+        let template: EventTemplate = serde_json::from_str(
+            r#"{"kind":1,"content":"Hello \"rana\"\n","tags":[["t","pow"],["nonce","1","1"]],"created_at":1700000000}"#,
+        )
+        .unwrap();
+        let keys = Keys::new(
+            SecretKey::from_str("3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683")
+                .unwrap(),
+        );
+        let mut event = mine_event(&template, keys.public_key(), 10, 2, &AtomicBool::new(false))
+            .event
+            .unwrap();
+        event.sign(&keys).unwrap();
+
+        // The old nonce tag is replaced, and the event is valid for nostr
+        let event = Event::from_json(serde_json::to_string(&event).unwrap()).unwrap();
+        event.verify().unwrap();
+        assert!(get_leading_zero_bits(event.id.as_bytes()) >= 10);
+        let tags: Vec<_> = event.tags.iter().map(Tag::as_vec).collect();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[1][0], "nonce");
+        assert_eq!(tags[1][2], "10");
+
+        // A cancelled search gives up, however hard the difficulty
+        let cancelled = AtomicBool::new(true);
+        let mining = mine_event(&template, keys.public_key(), 255, 2, &cancelled);
+        assert!(mining.event.is_none());
+    }
+
+    #[test]
+    fn nip06_accounts() {
+        let mnemonic = Mnemonic::from_str(
+            "leader monkey parrot ring guide accident before fence cannon height naive bean",
+        )
+        .unwrap();
+        let master = master_key(&mnemonic, "");
+        let keys = derive_keys(&master, 0, 0).unwrap();
+        assert_eq!(
+            keys.secret_key().unwrap().display_secret().to_string(),
+            "7f7ff03d123792d6ac594bfa67bf6d0c0ab55b6b1fdb6249303fe861f1ccba9a"
+        );
+        let path = DerivationPath::from_str("m/44'/1237'/3'/0/7").unwrap();
+        let child = master.derive_priv(SECP256K1, &path).unwrap();
+        assert_eq!(
+            derive_keys(&master, 3, 7).unwrap().secret_key().unwrap(),
+            child.private_key
+        );
+        assert!(derive_keys(&master, 1 << 31, 0).is_err());
+
+        assert_eq!(parse_accounts("2"), Ok(2..=2));
+        assert_eq!(parse_accounts("0-4"), Ok(0..=4));
+        assert!(parse_accounts("4-0").is_err());
+        assert!(parse_accounts("2147483648").is_err());
+    }
+
+    #[test]
+    fn scanned_derivations_can_be_restored() {
+        let mnemonic = Mnemonic::from_str(
+            "leader monkey parrot ring guide accident before fence cannon height naive bean",
+        )
+        .unwrap();
+        let master = master_key(&mnemonic, "");
+        for (scan, position) in [(DerivationScan::Account, 2), (DerivationScan::Index, 4)] {
+            let job = MiningJob::builder()
+                .matcher(HexPrefixMatcher::new("ab"))
+                .key_source(KeySource::Derivation {
+                    mnemonic: mnemonic.clone(),
+                    passphrase: String::new(),
+                    scan,
+                })
+                .threads(2)
+                .max_matches(1)
+                .build();
+            let mut found = None;
+            Miner::run(job, |event| {
+                if let MiningEvent::Found(key) = event {
+                    found = Some(key);
+                }
+            });
+            let found = found.unwrap();
+            assert_eq!(found.mnemonic, Some(mnemonic.clone()));
+
+            // The path of the key found gives it back
+            let path = found.derivation_path.unwrap();
+            let (ChildNumber::Hardened { index } | ChildNumber::Normal { index }) = path[position];
+            let keys = match scan {
+                DerivationScan::Account => derive_keys(&master, index, 0),
+                DerivationScan::Index => derive_keys(&master, 0, index),
+            };
+            assert_eq!(keys.unwrap().public_key(), found.keys.public_key());
+            assert!(found.keys.public_key().to_string().starts_with("ab"));
+        }
+    }
+
+    #[test]
+    fn mnemonic_keys_match_nip06() {
+        // 24 words are longer than a SHA-512 block, and the passphrase and the
+        // Japanese words are not normalized
+        for (word_count, passphrase, language) in [
+            (12, "", Language::English),
+            (24, "contraseña", Language::Spanish),
+            (24, "", Language::Japanese),
+        ] {
+            let key_source = KeySource::Mnemonic {
+                word_count,
+                passphrase: passphrase.to_string(),
+                language,
+            };
+            let mut stream = key_source.stream(Some(42), 0, 1);
+            let public_key = stream.next_public_key().unwrap();
+            let (keys, mnemonic) = stream.keys();
+            let mnemonic = mnemonic.unwrap();
+            assert_eq!(mnemonic.word_count(), word_count);
+            assert_eq!(mnemonic.language(), language);
+            let expected =
+                Keys::from_mnemonic(mnemonic.to_string(), Some(passphrase.to_string())).unwrap();
+            assert_eq!(keys.secret_key().unwrap(), expected.secret_key().unwrap());
+            assert_eq!(public_key, expected.public_key());
+        }
+    }
+
+    #[test]
+    fn mnemonic_diagnostics() {
+        let words =
+            "leader monkey parrot ring guide accident before fence cannon height naive bean";
+        assert_eq!(
+            parse_mnemonic(&format!("  Leader  MONKEY{}\n", &words[13..]), None),
+            Ok(Mnemonic::from_str(words).unwrap())
+        );
+        assert_eq!(
+            parse_mnemonic(&words.replace("accident", "acident"), None),
+            Err(RanaError::UnknownWords(vec![UnknownWord {
+                position: 6,
+                word: "acident".to_string(),
+                suggestions: vec![
+                    "accident".to_string(),
+                    "acid".to_string(),
+                    "ancient".to_string()
+                ],
+            }]))
+        );
+        assert_eq!(
+            parse_mnemonic(&words.replace(" bean", ""), None),
+            Err(RanaError::MnemonicWordCount(11))
+        );
+        assert_eq!(
+            parse_mnemonic(&words.replace("naive bean", "bean naive"), None),
+            Err(RanaError::MnemonicChecksum)
+        );
+    }
+
+    #[test]
+    fn mnemonic_languages() {
+        let entropy = [7; 16];
+        for language in [Language::Spanish, Language::Japanese, Language::French] {
+            let mnemonic = Mnemonic::from_entropy_in(language, &entropy).unwrap();
+            let phrase = mnemonic.to_string().nfc().collect::<String>();
+            assert_eq!(parse_mnemonic(&phrase, None), Ok(mnemonic.clone()));
+            assert_eq!(parse_mnemonic(&phrase, Some(language)), Ok(mnemonic));
+        }
+        let japanese = Mnemonic::from_entropy_in(Language::Japanese, &entropy)
+            .unwrap()
+            .to_string()
+            .replace(' ', "\u{3000}");
+        assert!(parse_mnemonic(&japanese, None).is_ok());
+        assert!(matches!(
+            parse_mnemonic(&japanese, Some(Language::English)),
+            Err(RanaError::UnknownWords(_))
+        ));
+    }
 }
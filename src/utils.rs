@@ -3,32 +3,6 @@ use nostr::bip39::Mnemonic;
 use nostr::prelude::*;
 use qrcode::render::unicode;
 use qrcode::QrCode;
-use std::time::Instant;
-
-/// Benchmark the cores capabilities for key generation
-pub fn benchmark_cores(cores: usize, pow_difficulty: u8) {
-    let mut hashes_per_second_per_core = 0;
-
-    println!("Benchmarking a single core for 5 seconds...");
-    let now = Instant::now();
-    let mut rng = rand::thread_rng();
-    loop {
-        let (_secret_key, public_key) = SECP256K1.generate_keypair(&mut rng);
-        let (xonly_public_key, _) = public_key.x_only_public_key();
-        get_leading_zero_bits(&xonly_public_key.serialize());
-        hashes_per_second_per_core += 1;
-        if now.elapsed().as_secs() > 5 {
-            break;
-        }
-    }
-    hashes_per_second_per_core /= 10;
-    println!("A single core can mine roughly {hashes_per_second_per_core} h/s!");
-
-    let estimated_hashes = 2_u128.pow(pow_difficulty as u32);
-    println!("Searching for prefix of {pow_difficulty} specific bits");
-    let estimate = estimated_hashes as f32 / hashes_per_second_per_core as f32 / cores as f32;
-    println!("This is estimated to take about {estimate} seconds");
-}
 
 /// Print private and public keys to the output
 pub fn print_keys(
@@ -61,19 +35,6 @@ pub fn print_keys(
     Ok(())
 }
 
-pub fn calculate_string_similarity(target: &str, candidate: &str) -> f64 {
-    let min_len = std::cmp::min(target.len(), candidate.len());
-    let matching = target
-        .chars()
-        .zip(candidate.chars())
-        .take(min_len)
-        .filter(|(a, b)| a == b)
-        .count();
-    
-    matching as f64 / target.len() as f64
-}
-
-
 #[inline]
 pub fn get_leading_zero_bits(bytes: &[u8]) -> u8 {
     let mut res = 0_u8;
@@ -89,8 +50,11 @@ pub fn get_leading_zero_bits(bytes: &[u8]) -> u8 {
 }
 
 pub fn print_qr(secret_key: SecretKey) -> Result<()> {
-    let nsec = secret_key.to_bech32()?;
-    let code = QrCode::new(nsec)?;
+    print_qr_code(&secret_key.to_bech32()?)
+}
+
+pub fn print_qr_code(data: &str) -> Result<()> {
+    let code = QrCode::new(data)?;
     let qr = code
         .render::<unicode::Dense1x2>()
         .dark_color(unicode::Dense1x2::Light)